@@ -0,0 +1,272 @@
+//! Minimal HTTP admin interface, protected by a bearer token.
+//!
+//! Routes:
+//! - `GET /sessions` lists all sessions, one per line (`<id> <players> <owner> <editor collab>`)
+//! - `POST /kick/<account_id>` disconnects a player
+//! - `POST /announce` sends the request body as an announcement to all players in every session
+//! - `POST /udp/enable`, `POST /udp/disable` toggle whether new UDP connections are accepted
+//! - `POST /snapshot/<session_id>` writes a binary snapshot of a session to `snapshot_dir`, responds with the file path
+
+use std::{net::SocketAddr, time::Duration};
+
+use server_shared::qunet::server::WeakServerHandle;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    handler::{AuditActor, ConnectionHandler},
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    },
+};
+
+const MAX_HEADER_SIZE: usize = 8192;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_BODY_SIZE: usize = 4096;
+
+struct Request {
+    method: String,
+    path: String,
+    token: Option<String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn new(status: u16, body: impl Into<String>) -> Self {
+        Self { status, body: body.into() }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            404 => "Not Found",
+            408 => "Request Timeout",
+            413 => "Payload Too Large",
+            500 => "Internal Server Error",
+            503 => "Service Unavailable",
+            _ => "Unknown",
+        }
+    }
+}
+
+pub async fn run(address: SocketAddr, token: String, server: WeakServerHandle<ConnectionHandler>) {
+    let listener = match TcpListener::bind(address).await {
+        Ok(x) => x,
+        Err(e) => {
+            error!("failed to bind the admin API to {address}: {e}");
+            return;
+        }
+    };
+
+    info!("- Admin API listening on: {address}");
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("admin API failed to accept a connection: {e}");
+                continue;
+            }
+        };
+
+        let token = token.clone();
+        let server = server.clone();
+
+        crate::tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &token, &server).await {
+                debug!("admin API connection from {peer} failed: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    token: &str,
+    server: &WeakServerHandle<ConnectionHandler>,
+) -> std::io::Result<()> {
+    // a peer that trickles bytes or sends nothing would otherwise keep this task alive forever
+    let request = match crate::tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream)).await {
+        Ok(result) => result?,
+        Err(_) => Err(Response::new(408, "timed out reading the request")),
+    };
+
+    let response = match request {
+        Ok(req) => {
+            if req.token.as_deref().is_some_and(|t| token_matches(t, token)) {
+                match server.upgrade() {
+                    Some(server) => route(server.handler(), &req).await,
+                    None => Response::new(503, "server is shutting down"),
+                }
+            } else {
+                Response::new(401, "invalid or missing token")
+            }
+        }
+
+        Err(resp) => resp,
+    };
+
+    let out = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        response.body.len(),
+        response.body
+    );
+
+    stream.write_all(out.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Result<Request, Response>> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+
+        if buf.len() > MAX_HEADER_SIZE {
+            return Ok(Err(Response::new(413, "headers too large")));
+        }
+
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(Err(Response::new(400, "incomplete request")));
+        }
+
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let Ok(head) = std::str::from_utf8(&buf[..header_end]) else {
+        return Ok(Err(Response::new(400, "invalid request")));
+    };
+
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+
+    let (Some(method), Some(path)) = (request_line.next(), request_line.next()) else {
+        return Ok(Err(Response::new(400, "invalid request line")));
+    };
+
+    let mut token = None;
+    let mut content_length = 0usize;
+
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+
+        let value = value.trim();
+
+        if name.eq_ignore_ascii_case("authorization") {
+            token = value.strip_prefix("Bearer ").map(str::to_owned);
+        } else if name.eq_ignore_ascii_case("content-length") {
+            content_length = value.parse().unwrap_or(0);
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Ok(Err(Response::new(413, "body too large")));
+    }
+
+    let mut body = buf[header_end + 4..].to_vec();
+    while body.len() < content_length {
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+
+        body.extend_from_slice(&chunk[..n]);
+    }
+
+    body.truncate(content_length);
+
+    Ok(Ok(Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        token,
+        body,
+    }))
+}
+
+async fn route(handler: &ConnectionHandler, req: &Request) -> Response {
+    match (req.method.as_str(), req.path.as_str()) {
+        ("GET", "/sessions") => {
+            let mut out = String::new();
+
+            for s in handler.session_summaries() {
+                out.push_str(&format!(
+                    "{} {} {} {}\n",
+                    s.id, s.player_count, s.owner, s.editor_collab
+                ));
+            }
+
+            Response::new(200, out)
+        }
+
+        ("POST", "/announce") => {
+            let Ok(text) = std::str::from_utf8(&req.body) else {
+                return Response::new(400, "announcement must be valid UTF-8");
+            };
+
+            let text = text.trim();
+            if text.is_empty() {
+                return Response::new(400, "announcement is empty");
+            }
+
+            let sessions = handler.broadcast_announcement(text);
+            Response::new(200, format!("sent to {sessions} sessions"))
+        }
+
+        ("POST", "/udp/enable") => {
+            handler.set_udp_connections_enabled(true);
+            Response::new(200, "UDP connections enabled")
+        }
+
+        ("POST", "/udp/disable") => {
+            handler.set_udp_connections_enabled(false);
+            Response::new(200, "UDP connections disabled")
+        }
+
+        ("POST", path) if path.starts_with("/kick/") => {
+            let Ok(account_id) = path["/kick/".len()..].parse::<i32>() else {
+                return Response::new(400, "invalid account ID");
+            };
+
+            if handler.kick_player(AuditActor::Admin, account_id, "Kicked by an administrator") {
+                Response::new(200, "kicked")
+            } else {
+                Response::new(404, "player not found")
+            }
+        }
+
+        ("POST", path) if path.starts_with("/snapshot/") => {
+            let Ok(session_id) = path["/snapshot/".len()..].parse::<u64>() else {
+                return Response::new(400, "invalid session ID");
+            };
+
+            match handler.write_session_snapshot(session_id).await {
+                Some(Ok(path)) => Response::new(200, path.display().to_string()),
+                Some(Err(e)) => Response::new(500, format!("failed to write snapshot: {e}")),
+                None => Response::new(404, "session not found"),
+            }
+        }
+
+        _ => Response::new(404, "unknown route"),
+    }
+}
+
+/// Compares tokens without exiting early on the first mismatched byte
+fn token_matches(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given.bytes().zip(expected.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::VecDeque,
     pin::Pin,
     sync::{
         OnceLock,
@@ -7,7 +8,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-use crate::handler::ConnectionHandler;
+use crate::handler::{AuditActor, ConnectionHandler};
 
 use super::{data, server_role::ServerRole};
 use parking_lot::Mutex;
@@ -26,10 +27,64 @@ pub struct BridgeHandler {
     server_url: String,
     password: String,
     authenticated: AtomicBool,
+    /// Whether the login has succeeded at least once since launch
+    ever_authenticated: AtomicBool,
     server_handle: OnceLock<WeakServerHandle<ConnectionHandler>>,
     reconnect_attempt: AtomicUsize,
     conn_started: Mutex<Option<Instant>>,
     scheduled_status: AtomicBool,
+    status_interval: Duration,
+    breaker: CircuitBreaker,
+    /// Set when the central server sent keys we can't use, reconnecting right away would just fail the same way
+    invalid_keys: AtomicBool,
+}
+
+/// Stops reconnection attempts for a while if the central server keeps failing,
+/// e.g. when it's stuck in a crash loop.
+pub struct CircuitBreaker {
+    threshold: usize,
+    window: Duration,
+    cooldown: Duration,
+    failures: Mutex<VecDeque<Instant>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: usize, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            window,
+            cooldown,
+            failures: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Records a failed attempt, returns `true` if the breaker has tripped.
+    /// The failure history is cleared when tripping, so the next attempt starts fresh.
+    fn record_failure(&self) -> bool {
+        if self.threshold == 0 {
+            return false;
+        }
+
+        let now = Instant::now();
+        let mut failures = self.failures.lock();
+
+        while failures.front().is_some_and(|t| now.duration_since(*t) > self.window) {
+            failures.pop_front();
+        }
+
+        failures.push_back(now);
+
+        if failures.len() >= self.threshold {
+            failures.clear();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn reset(&self) {
+        self.failures.lock().clear();
+    }
 }
 
 impl EventHandler for BridgeHandler {
@@ -39,6 +94,7 @@ impl EventHandler for BridgeHandler {
         self.conn_started.lock().replace(Instant::now());
 
         self.reconnect_attempt.store(0, Ordering::Relaxed);
+        self.breaker.reset();
 
         // send srvc handshake
         let mut writer = HeapByteWriter::new();
@@ -73,25 +129,14 @@ impl EventHandler for BridgeHandler {
         // schedule a status message to be sent every once in a while
         if !self.scheduled_status.swap(true, Ordering::Relaxed) {
             let client = client.clone();
-            let server = self.server();
+            let interval = self.status_interval;
 
             crate::tokio::spawn(async move {
-                let mut interval = crate::tokio::time::interval(Duration::from_secs(30));
+                let mut interval = crate::tokio::time::interval(interval);
 
                 loop {
                     interval.tick().await;
-
-                    let h = server.handler();
-                    let data = h.refresh_status_data();
-
-                    let buf = data::encode_message_unsafe!(client.handler(), 128, msg => {
-                        data.encode(msg.init_status());
-                    })
-                    .expect("failed to encode status message");
-
-                    if client.connected() {
-                        client.send_data_bufkind(buf);
-                    }
+                    client.handler().report_stats(&client);
                 }
             });
         }
@@ -104,6 +149,14 @@ impl EventHandler for BridgeHandler {
         let conn_duration =
             self.conn_started.lock().take().map_or(Duration::ZERO, |start| start.elapsed());
 
+        if self.invalid_keys.swap(false, Ordering::Relaxed) {
+            let cooldown = self.breaker.cooldown;
+            error!("Central server keys are invalid, waiting {cooldown:?} before reconnecting");
+
+            self.delay_reconnect(client, cooldown).await;
+            return;
+        }
+
         // if we disconnected too quickly, wait before trying to reconnect (smaller deadline if was authenticated)
         let deadline = if was_authenticated {
             Duration::from_secs(2)
@@ -138,7 +191,12 @@ impl EventHandler for BridgeHandler {
                 let script_key = msg.get_script_key()?.to_str()?;
 
                 if let Err(e) = self.server().handler().init_bridge_things(token_key, token_expiry, script_key) {
-                    error!("Failed to initialize token issuer: {e}");
+                    error!("==============================================================");
+                    error!("The central server sent invalid keys: {e}");
+                    error!("This is a configuration problem on the central server, not a connection issue.");
+                    error!("==============================================================");
+
+                    self.invalid_keys.store(true, Ordering::Relaxed);
                     client.disconnect();
                     return Ok(());
                 }
@@ -190,9 +248,11 @@ impl EventHandler for BridgeHandler {
 
                 unpacked_data.reset();
 
-                if let Some(user) = self.server().handler().find_client(account_id) {
-                    user.disconnect("disconnected by central server");
-                }
+                self.server().handler().kick_player(
+                    AuditActor::CentralServer,
+                    account_id,
+                    "disconnected by central server",
+                );
             },
 
             ReloadConfig(msg) => {
@@ -209,15 +269,24 @@ impl EventHandler for BridgeHandler {
 }
 
 impl BridgeHandler {
-    pub fn new(server_url: String, password: String) -> Self {
+    pub fn new(
+        server_url: String,
+        password: String,
+        status_interval: Duration,
+        breaker: CircuitBreaker,
+    ) -> Self {
         Self {
             server_url,
             password,
             authenticated: AtomicBool::new(false),
+            ever_authenticated: AtomicBool::new(false),
             server_handle: OnceLock::new(),
             reconnect_attempt: AtomicUsize::new(0),
             conn_started: Mutex::new(None),
             scheduled_status: AtomicBool::new(false),
+            status_interval,
+            breaker,
+            invalid_keys: AtomicBool::new(false),
         }
     }
 
@@ -244,16 +313,51 @@ impl BridgeHandler {
         self.authenticated.load(Ordering::Relaxed)
     }
 
+    pub fn ever_authenticated(&self) -> bool {
+        self.ever_authenticated.load(Ordering::Relaxed)
+    }
+
     fn set_authenticated(&self, authenticated: bool) -> bool {
+        if authenticated {
+            self.ever_authenticated.store(true, Ordering::Relaxed);
+        }
+
         self.authenticated.swap(authenticated, Ordering::Relaxed)
     }
 
+    /// Gathers client and session statistics and sends them to the central server.
+    /// The statistics are always refreshed, but only sent if the bridge is authenticated.
+    fn report_stats(&self, client: &ClientHandle<Self>) {
+        let data = self.server().handler().refresh_status_data();
+
+        if !client.connected() || !self.authenticated() {
+            return;
+        }
+
+        let buf = match data::encode_message_unsafe!(self, 128, msg => {
+            data.encode(msg.init_status());
+        }) {
+            Ok(buf) => buf,
+            Err(e) => {
+                error!("failed to encode status message: {e}");
+                return;
+            }
+        };
+
+        client.send_data_bufkind(buf);
+    }
+
     #[must_use]
     fn on_connection_error_helper<'a>(
         &'a self,
         client: &'a ClientHandle<Self>,
         err: ConnectionError,
     ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        if self.breaker.record_failure() {
+            error!("Connection to central server failed: {err}");
+            return self.trip_breaker(client);
+        }
+
         let attempt_count = self.reconnect_attempt.fetch_add(1, Ordering::Relaxed) + 1;
         let wait_time = Duration::from_secs(2u64.pow(attempt_count.clamp(1, 6) as u32));
 
@@ -262,6 +366,24 @@ impl BridgeHandler {
         self.delay_reconnect(client, wait_time)
     }
 
+    #[must_use]
+    fn trip_breaker<'a>(
+        &'a self,
+        client: &'a ClientHandle<Self>,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send + 'a>> {
+        let cooldown = self.breaker.cooldown;
+
+        error!("==============================================================");
+        error!(
+            "Central server failed {} times in the last {:?}, pausing reconnection for {cooldown:?}",
+            self.breaker.threshold, self.breaker.window
+        );
+        error!("==============================================================");
+
+        self.reconnect_attempt.store(0, Ordering::Relaxed);
+        self.delay_reconnect(client, cooldown)
+    }
+
     #[must_use]
     fn delay_reconnect<'a>(
         &'a self,
@@ -318,6 +440,12 @@ impl BridgeHandler {
 
         if data.is_banned {
             if let Some(user) = handler.find_client(data.account_id) {
+                handler.audit_log(
+                    AuditActor::CentralServer,
+                    "ban",
+                    data.account_id,
+                    "banned on the central server",
+                );
                 user.terminate();
             }
         } else {
@@ -1,18 +1,24 @@
 /// Bridge to the central server.
 ///
+use std::time::Duration;
+
 use server_shared::qunet::{
     client::{Client, ClientHandle, ClientOutcome, ConnectionError},
     server::WeakServerHandle,
 };
 use thiserror::Error;
 
-use crate::{bridge::handler::BridgeHandler, config::Config, handler::ConnectionHandler};
+use crate::{
+    bridge::handler::{BridgeHandler, CircuitBreaker},
+    config::Config,
+    handler::ConnectionHandler,
+};
 
 #[allow(unused)]
 mod data;
 mod handler;
 mod server_role;
-pub use server_role::ServerRole;
+pub use server_role::{RolePermissions, ServerRole};
 
 #[derive(Debug, Error)]
 pub enum BridgeError {
@@ -31,6 +37,12 @@ impl Bridge {
         let handler = BridgeHandler::new(
             config.central_server_url.clone(),
             config.central_server_password.clone(),
+            Duration::from_secs(config.status_report_interval),
+            CircuitBreaker::new(
+                config.bridge_breaker_threshold,
+                Duration::from_secs(config.bridge_breaker_window),
+                Duration::from_secs(config.bridge_breaker_cooldown),
+            ),
         );
 
         let mut builder = Client::builder().with_event_handler(handler);
@@ -64,4 +76,15 @@ impl Bridge {
     pub fn is_connecting(&self) -> bool {
         self.client.connecting()
     }
+
+    /// Whether the login to the central server succeeded on the current connection.
+    /// Being connected is not enough to validate player tokens, this is.
+    pub fn is_authenticated(&self) -> bool {
+        self.client.handler().authenticated()
+    }
+
+    /// Whether the login to the central server has ever succeeded since launch
+    pub fn has_authenticated(&self) -> bool {
+        self.client.handler().ever_authenticated()
+    }
 }
@@ -1,11 +1,51 @@
+use std::ops::{BitOr, BitOrAssign};
+
 use server_shared::encoding::{DataDecodeError, heapless_str_from_reader};
 
 use super::data;
 
+/// Set of permission flags granted by a role. Flags of multiple roles are combined with `|`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RolePermissions(u8);
+
+impl RolePermissions {
+    pub const NONE: Self = Self(0);
+    pub const MODERATE: Self = Self(1 << 0);
+    /// Allows using voice chat even if the central server did not (yet) allow it for this user
+    pub const VOICE_ALWAYS: Self = Self(1 << 1);
+    /// Allows creating sessions past the `max_sessions` and `max_sessions_per_owner` limits
+    pub const BYPASS_PLAYER_CAP: Self = Self(1 << 2);
+    /// Allows sending announcements to other players
+    pub const BROADCAST: Self = Self(1 << 3);
+
+    pub const ALL: Self = Self(
+        Self::MODERATE.0 | Self::VOICE_ALWAYS.0 | Self::BYPASS_PLAYER_CAP.0 | Self::BROADCAST.0,
+    );
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for RolePermissions {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for RolePermissions {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
 pub struct ServerRole {
     pub id: u8,
     pub string_id: heapless::String<32>,
     pub can_moderate: bool,
+    pub permissions: RolePermissions,
 }
 
 impl ServerRole {
@@ -14,6 +54,19 @@ impl ServerRole {
         let string_id = heapless_str_from_reader(reader.get_string_id()?)?;
         let can_moderate = reader.get_can_moderate();
 
-        Ok(Self { id, string_id, can_moderate })
+        // the central server currently only sends the moderation bit, moderators are granted every
+        // finer-grained permission until the rest of the bits are sent separately
+        let permissions = if can_moderate {
+            RolePermissions::ALL
+        } else {
+            RolePermissions::NONE
+        };
+
+        Ok(Self {
+            id,
+            string_id,
+            can_moderate,
+            permissions,
+        })
     }
 }
@@ -2,44 +2,195 @@ use std::{
     num::NonZero,
     sync::{
         Arc, OnceLock,
-        atomic::{AtomicBool, AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
     },
+    time::{Duration, Instant},
 };
 
-use parking_lot::Mutex;
+use arc_swap::ArcSwap;
+use parking_lot::{Mutex, MutexGuard};
+use rustc_hash::FxHashSet;
 use server_shared::{
     MultiColor, UserSettings,
     data::PlayerIconData,
     events::{EventRateLimiter, EventRateLimiterOptions},
-    qunet::transport::RateLimiter,
+    qunet::{buffers::HeapByteWriter, transport::RateLimiter},
     token_issuer::TokenData,
 };
+use tracing::warn;
+
+use crate::{bridge::RolePermissions, events::EventEncoder, session_manager::GameSession};
+
+/// The transport a client is connected over
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConnectionKind {
+    Tcp,
+    Udp,
+    Quic,
+    #[default]
+    Other,
+}
 
-use crate::{events::EventEncoder, session_manager::GameSession};
+impl ConnectionKind {
+    pub const COUNT: usize = 4;
+
+    pub fn parse(kind: &str) -> Self {
+        if kind.eq_ignore_ascii_case("tcp") {
+            Self::Tcp
+        } else if kind.eq_ignore_ascii_case("udp") {
+            Self::Udp
+        } else if kind.eq_ignore_ascii_case("quic") {
+            Self::Quic
+        } else {
+            Self::Other
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct SpecialUserData {
     pub roles: heapless::Vec<u8, 64>,
     pub name_color: Option<MultiColor>,
+    /// Permissions of all roles combined
+    pub permissions: RolePermissions,
+}
+
+/// Which way traffic goes, each direction has its own bandwidth budget so that a busy session
+/// sending a lot to a client can't get the client's own messages dropped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrafficDirection {
+    Ingress,
+    Egress,
+}
+
+impl TrafficDirection {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Ingress => "incoming",
+            Self::Egress => "outgoing",
+        }
+    }
+}
+
+/// Result of recording traffic for a client
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BandwidthState {
+    Ok,
+    /// The client just went over its budget
+    Throttled,
+    /// The client was already over its budget
+    StillThrottled,
+}
+
+/// Tracks bytes sent or received over a sliding one second window, approximated by weighting
+/// the previous window by how much of it still overlaps with the last second.
+struct BandwidthTracker {
+    limit: u64,
+    window_start: Instant,
+    current: u64,
+    previous: u64,
+    throttled: bool,
+}
+
+impl BandwidthTracker {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    fn record(&mut self, bytes: usize) -> BandwidthState {
+        if self.limit == 0 {
+            return BandwidthState::Ok;
+        }
+
+        let now = Instant::now();
+        let mut elapsed = now.duration_since(self.window_start);
+
+        if elapsed >= Self::WINDOW {
+            self.previous = if elapsed >= Self::WINDOW * 2 { 0 } else { self.current };
+            self.current = 0;
+            self.window_start = now;
+            elapsed = Duration::ZERO;
+        }
+
+        self.current += bytes as u64;
+
+        let overlap = 1.0 - elapsed.as_secs_f64() / Self::WINDOW.as_secs_f64();
+        let rate = self.current as f64 + self.previous as f64 * overlap;
+
+        let was_throttled = self.throttled;
+        self.throttled = rate > self.limit as f64;
+
+        match (self.throttled, was_throttled) {
+            (false, _) => BandwidthState::Ok,
+            (true, false) => BandwidthState::Throttled,
+            (true, true) => BandwidthState::StillThrottled,
+        }
+    }
+}
+
+impl Default for BandwidthTracker {
+    fn default() -> Self {
+        Self {
+            limit: 0,
+            window_start: Instant::now(),
+            current: 0,
+            previous: 0,
+            throttled: false,
+        }
+    }
 }
 
 pub struct ClientData {
+    connection_kind: ConnectionKind,
+    connected_at: Instant,
     account_data: OnceLock<TokenData>,
     session_id: AtomicU64,
     session: Mutex<Option<Arc<GameSession>>>,
     icons: Mutex<PlayerIconData>,
-    special_data: OnceLock<SpecialUserData>,
+    special_data: ArcSwap<Option<SpecialUserData>>,
+    name_color_size: AtomicUsize,
+    /// Role string from the login token, kept if the client logged in before server roles were known
+    pending_roles: Mutex<Option<String>>,
     is_moderator: AtomicBool,
     deauthorized: AtomicBool,
+    reliable_player_data: AtomicBool,
+    frozen: AtomicBool,
+    radar_enabled: AtomicBool,
+    spoof_violations: AtomicU32,
     settings: Mutex<UserSettings>,
     last_voice_msg: Mutex<RateLimiter>,
     last_quick_chat_msg: Mutex<RateLimiter>,
+    last_text_chat_msg: Mutex<RateLimiter>,
+    data_request_limiter: Mutex<RateLimiter>,
+    session_change_limiter: Mutex<RateLimiter>,
+    icon_update_limiter: Mutex<RateLimiter>,
+    /// Set when an icon change was not broadcast yet because of the rate limit
+    icon_refresh_pending: AtomicBool,
+    ingress_bandwidth: Mutex<BandwidthTracker>,
+    egress_bandwidth: Mutex<BandwidthTracker>,
+    /// Reused between player data packets for the spatial index query
+    nearby_players: Mutex<FxHashSet<i32>>,
+    #[cfg(feature = "debug-netsim")]
+    netsim_rng: Mutex<u64>,
 
     event_encoder: OnceLock<EventEncoder>,
     event_limiter: Mutex<EventRateLimiter>,
 }
 
 impl ClientData {
+    pub fn new(connection_kind: ConnectionKind) -> Self {
+        Self {
+            connection_kind,
+            ..Default::default()
+        }
+    }
+
+    pub fn connection_kind(&self) -> ConnectionKind {
+        self.connection_kind
+    }
+
+    pub fn connected_at(&self) -> Instant {
+        self.connected_at
+    }
+
     pub fn account_data(&self) -> Option<&TokenData> {
         if self.deauthorized.load(Ordering::Relaxed) {
             return None;
@@ -92,6 +243,8 @@ impl ClientData {
 
     /// Sets the session for this client, returning the previous session if it existed.
     pub fn set_session(&self, session: Arc<GameSession>) -> Option<Arc<GameSession>> {
+        // has to be requested again for every session
+        self.reliable_player_data.store(false, Ordering::Relaxed);
         self.session_id.store(session.id, Ordering::Relaxed);
         let mut old = self.session.lock();
         old.replace(session)
@@ -124,14 +277,64 @@ impl ClientData {
         *self.settings.lock()
     }
 
-    pub fn set_special_data(&self, roles: heapless::Vec<u8, 64>, name_color: Option<MultiColor>) {
-        self.special_data
-            .set(SpecialUserData { roles, name_color })
-            .expect("attempting to set user roles twice");
+    /// `account_id` is only used for logging, since this is called during login before the account data is set.
+    pub fn set_special_data(
+        &self,
+        account_id: i32,
+        roles: heapless::Vec<u8, 64>,
+        name_color: Option<MultiColor>,
+        permissions: RolePermissions,
+    ) {
+        self.update_special_data(
+            account_id,
+            Some(SpecialUserData { roles, name_color, permissions }),
+        );
+    }
+
+    /// Replaces the client's roles and name color, can be called at any point while the client is connected.
+    /// A name color that does not fit in `MAX_NAME_COLOR_SIZE` bytes when encoded is dropped.
+    pub fn update_special_data(&self, account_id: i32, mut data: Option<SpecialUserData>) {
+        let mut color_size = 0;
+
+        if let Some(data) = &mut data
+            && let Some(color) = &data.name_color
+        {
+            match name_color_size(color) {
+                Some(size) => color_size = size,
+                None => {
+                    warn!(
+                        "[{account_id}] name color is longer than {MAX_NAME_COLOR_SIZE} bytes when encoded, ignoring it"
+                    );
+
+                    data.name_color = None;
+                }
+            }
+        }
+
+        self.name_color_size.store(color_size, Ordering::Relaxed);
+        self.special_data.store(Arc::new(data));
+    }
+
+    /// Encoded size of the client's name color, 0 if it has none
+    pub fn name_color_size(&self) -> usize {
+        self.name_color_size.load(Ordering::Relaxed)
+    }
+
+    pub fn special_data(&self) -> Arc<Option<SpecialUserData>> {
+        self.special_data.load_full()
     }
 
-    pub fn special_data(&self) -> Option<&SpecialUserData> {
-        self.special_data.get()
+    pub fn set_pending_roles(&self, roles: String) {
+        *self.pending_roles.lock() = Some(roles);
+    }
+
+    pub fn take_pending_roles(&self) -> Option<String> {
+        self.pending_roles.lock().take()
+    }
+
+    /// Returns whether any of the client's roles grants the given permission.
+    pub fn has_permission(&self, flag: RolePermissions) -> bool {
+        self.special_data.load().as_ref().as_ref().is_some_and(|d| d.permissions.contains(flag))
     }
 
     pub fn set_moderator(&self, is_mod: bool) {
@@ -142,6 +345,37 @@ impl ClientData {
         self.is_moderator.load(Ordering::Relaxed)
     }
 
+    /// Whether player data sent to this client should always be reliable, rather than only when it carries reliable events
+    pub fn set_reliable_player_data(&self, reliable: bool) {
+        self.reliable_player_data.store(reliable, Ordering::Relaxed);
+    }
+
+    pub fn reliable_player_data(&self) -> bool {
+        self.reliable_player_data.load(Ordering::Relaxed)
+    }
+
+    /// Frozen clients stay connected, but their player data isn't shown to anyone and their events are dropped
+    pub fn set_frozen(&self, frozen: bool) {
+        self.frozen.store(frozen, Ordering::Relaxed);
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::Relaxed)
+    }
+
+    pub fn set_radar_enabled(&self, enabled: bool) {
+        self.radar_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn radar_enabled(&self) -> bool {
+        self.radar_enabled.load(Ordering::Relaxed)
+    }
+
+    /// Records an attempt to send data on behalf of another account, returns the total amount of attempts so far
+    pub fn record_spoof_violation(&self) -> u32 {
+        self.spoof_violations.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
     pub fn try_voice_chat(&self) -> bool {
         self.last_voice_msg.lock().consume()
     }
@@ -150,6 +384,88 @@ impl ClientData {
         self.last_quick_chat_msg.lock().consume()
     }
 
+    pub fn try_text_chat(&self) -> bool {
+        self.last_text_chat_msg.lock().consume()
+    }
+
+    /// Returns whether the client may join another session now. Leaves are not limited, since every leave needs a join first.
+    pub fn try_session_change(&self) -> bool {
+        self.session_change_limiter.lock().consume()
+    }
+
+    /// Waits until the client may join another session, see `try_session_change`
+    pub async fn wait_session_change(&self) {
+        while !self.try_session_change() {
+            crate::tokio::time::sleep(Duration::from_nanos(SESSION_CHANGE_INTERVAL_NS)).await;
+        }
+    }
+
+    /// Returns whether an icon change should be broadcast now.
+    /// If not, the change is remembered and picked up by `try_pending_icon_refresh` once the limit allows it.
+    pub fn try_icon_update(&self) -> bool {
+        let allowed = self.icon_update_limiter.lock().consume();
+        self.icon_refresh_pending.store(!allowed, Ordering::Relaxed);
+        allowed
+    }
+
+    /// Returns whether a previously rate limited icon change should be broadcast now
+    pub fn try_pending_icon_refresh(&self) -> bool {
+        if !self.icon_refresh_pending.load(Ordering::Relaxed)
+            || !self.icon_update_limiter.lock().consume()
+        {
+            return false;
+        }
+
+        self.icon_refresh_pending.store(false, Ordering::Relaxed);
+        true
+    }
+
+    /// Sets how many metadata requests per second the client can make, allowing bursts of a full request list.
+    pub fn set_data_request_rate(&self, per_second: u64) {
+        *self.data_request_limiter.lock() =
+            RateLimiter::new_precise(1_000_000_000 / per_second.max(1), 64);
+    }
+
+    /// Consumes a token from the metadata request budget, returns `false` if the request should be ignored.
+    pub fn try_data_request(&self) -> bool {
+        self.data_request_limiter.lock().consume()
+    }
+
+    /// Sets how many bytes per second the client can send and receive, 0 means unlimited.
+    pub fn set_bandwidth_limits(&self, ingress: u64, egress: u64) {
+        self.ingress_bandwidth.lock().limit = ingress;
+        self.egress_bandwidth.lock().limit = egress;
+    }
+
+    /// Records `bytes` of traffic in the given direction, and returns whether that direction is now over its budget.
+    pub fn record_bandwidth(&self, direction: TrafficDirection, bytes: usize) -> BandwidthState {
+        match direction {
+            TrafficDirection::Ingress => self.ingress_bandwidth.lock().record(bytes),
+            TrafficDirection::Egress => self.egress_bandwidth.lock().record(bytes),
+        }
+    }
+
+    /// Decides whether to drop an outgoing packet, `loss` being the fraction of packets to drop
+    #[cfg(feature = "debug-netsim")]
+    pub fn netsim_should_drop(&self, loss: f32) -> bool {
+        if loss <= 0.0 {
+            return false;
+        }
+
+        // xorshift64, quality doesn't matter here
+        let mut rng = self.netsim_rng.lock();
+        *rng ^= *rng << 13;
+        *rng ^= *rng >> 7;
+        *rng ^= *rng << 17;
+
+        ((*rng >> 40) as f32 / (1u64 << 24) as f32) < loss
+    }
+
+    /// Returns the buffer used for collecting nearby players when building player data for this client
+    pub fn nearby_players_buffer(&self) -> MutexGuard<'_, FxHashSet<i32>> {
+        self.nearby_players.lock()
+    }
+
     pub fn event_encoder(&self) -> &EventEncoder {
         self.event_encoder.get().expect("event encoder not initialized")
     }
@@ -163,25 +479,72 @@ impl ClientData {
     }
 }
 
+/// Maximum encoded size of a name color, colors are encoded into a buffer of this size when sending player metadata
+pub const MAX_NAME_COLOR_SIZE: usize = 256;
+
+/// Returns the encoded size of the color, or `None` if it's larger than `MAX_NAME_COLOR_SIZE` bytes.
+fn name_color_size(color: &MultiColor) -> Option<usize> {
+    let mut writer = HeapByteWriter::new();
+    color.encode(&mut writer);
+
+    let size = writer.written().len();
+    (size <= MAX_NAME_COLOR_SIZE).then_some(size)
+}
+
 /// How often to refill a token in the voice chat rate limiter
 /// A single audio frame is 60ms, so setting this to 50ms gives some leeway even when client audio buffer is 1 frame
 const VOICE_INTERVAL_NS: u64 = 50_000_000;
 /// How often to refill a token in the quick chat rate limiter (2 seconds)
 const QUICK_CHAT_INTERVAL_NS: u64 = 2_000_000_000;
+const TEXT_CHAT_INTERVAL_NS: u64 = 1_000_000_000;
+/// How often to refill a token in the session join rate limiter
+const SESSION_CHANGE_INTERVAL_NS: u64 = 1_000_000_000;
+/// How often to refill a token in the icon update broadcast rate limiter
+const ICON_UPDATE_INTERVAL_NS: u64 = 1_000_000_000;
+/// Default refill interval of the metadata request limiter, overridden by the config on connect
+const DATA_REQUEST_INTERVAL_NS: u64 = 1_000_000_000 / 128;
 
 impl Default for ClientData {
     fn default() -> Self {
         Self {
+            connection_kind: ConnectionKind::default(),
+            connected_at: Instant::now(),
             account_data: OnceLock::new(),
             session_id: AtomicU64::new(0),
             session: Mutex::default(),
             icons: Mutex::default(),
-            special_data: OnceLock::new(),
+            special_data: ArcSwap::new(Arc::new(None)),
+            name_color_size: AtomicUsize::new(0),
+            pending_roles: Mutex::default(),
             is_moderator: AtomicBool::new(false),
             deauthorized: AtomicBool::new(false),
+            reliable_player_data: AtomicBool::new(false),
+            frozen: AtomicBool::new(false),
+            radar_enabled: AtomicBool::new(false),
+            spoof_violations: AtomicU32::new(0),
             settings: Mutex::default(),
             last_voice_msg: Mutex::new(RateLimiter::new_precise(VOICE_INTERVAL_NS, 5)),
             last_quick_chat_msg: Mutex::new(RateLimiter::new_precise(QUICK_CHAT_INTERVAL_NS, 1)),
+            last_text_chat_msg: Mutex::new(RateLimiter::new_precise(TEXT_CHAT_INTERVAL_NS, 3)),
+            data_request_limiter: Mutex::new(RateLimiter::new_precise(
+                DATA_REQUEST_INTERVAL_NS,
+                64,
+            )),
+            session_change_limiter: Mutex::new(RateLimiter::new_precise(
+                SESSION_CHANGE_INTERVAL_NS,
+                5,
+            )),
+            icon_update_limiter: Mutex::new(RateLimiter::new_precise(ICON_UPDATE_INTERVAL_NS, 3)),
+            icon_refresh_pending: AtomicBool::new(false),
+            ingress_bandwidth: Mutex::default(),
+            egress_bandwidth: Mutex::default(),
+            nearby_players: Mutex::default(),
+            #[cfg(feature = "debug-netsim")]
+            netsim_rng: Mutex::new({
+                use std::hash::{BuildHasher, RandomState};
+                // xorshift gets stuck at 0, so make sure the seed never is
+                RandomState::new().hash_one(Instant::now()) | 1
+            }),
             event_encoder: OnceLock::new(),
             event_limiter: Mutex::new(EventRateLimiter::new(EventRateLimiterOptions {
                 // very fair limits
@@ -191,3 +554,43 @@ impl Default for ClientData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_color(data: &ClientData, color: &str) {
+        data.set_special_data(
+            1,
+            heapless::Vec::new(),
+            Some(color.parse().unwrap()),
+            RolePermissions::NONE,
+        );
+    }
+
+    #[test]
+    fn fitting_name_color_is_kept() {
+        let color: MultiColor = "#ff0000".parse().unwrap();
+        let mut writer = HeapByteWriter::new();
+        color.encode(&mut writer);
+
+        let data = ClientData::default();
+        set_color(&data, "#ff0000");
+
+        assert!(data.special_data().as_ref().as_ref().unwrap().name_color.is_some());
+        assert_eq!(data.name_color_size(), writer.written().len());
+    }
+
+    #[test]
+    fn oversized_name_color_is_dropped() {
+        // a gradient takes 3 bytes per color, so this can't fit
+        let stops = vec!["#123456"; MAX_NAME_COLOR_SIZE / 3 + 1];
+
+        let data = ClientData::default();
+        set_color(&data, "#ff0000");
+        set_color(&data, &stops.join(">"));
+
+        assert!(data.special_data().as_ref().as_ref().unwrap().name_color.is_none());
+        assert_eq!(data.name_color_size(), 0);
+    }
+}
@@ -37,6 +37,11 @@ impl ClientStore {
         });
     }
 
+    /// Returns handles to all clients that are still connected
+    pub fn all(&self) -> Vec<ClientStateHandle> {
+        self.map.iter().filter_map(|x| x.upgrade()).collect()
+    }
+
     pub fn vacuum(&self) -> usize {
         let mut removed = 0;
 
@@ -3,7 +3,7 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, de::Error as _};
 use server_shared::{config::env_replace, logging::LoggerConfig};
 use thiserror::Error;
 use validator::Validate;
@@ -14,6 +14,35 @@ fn default_memory_usage() -> u32 {
     3
 }
 
+/// Maps a named memory usage preset to its numeric value
+fn memory_usage_preset(name: &str) -> Option<u32> {
+    match name.to_ascii_lowercase().as_str() {
+        "low" => Some(1),
+        "medium" => Some(3),
+        "high" => Some(7),
+        _ => None,
+    }
+}
+
+/// Accepts either a number or the name of a preset
+fn deserialize_memory_usage<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Number(u32),
+        Preset(String),
+    }
+
+    match Raw::deserialize(deserializer)? {
+        Raw::Number(n) => Ok(n),
+        Raw::Preset(name) => memory_usage_preset(&name).ok_or_else(|| {
+            D::Error::custom(format!(
+                "unknown memory usage preset '{name}', expected low, medium, high or a number from 1 to 11"
+            ))
+        }),
+    }
+}
+
 fn default_compression_level() -> u32 {
     3
 }
@@ -132,11 +161,149 @@ fn default_server_load_formula() -> Option<String> {
     None
 }
 
+fn default_status_report_interval() -> u64 {
+    30
+}
+
+fn default_stale_player_timeout_ms() -> u64 {
+    5000
+}
+
+fn default_data_requests_per_second() -> u64 {
+    128
+}
+
+fn default_max_voice_message_size() -> usize {
+    16384
+}
+
+fn default_max_sessions() -> usize {
+    0
+}
+
+fn default_max_sessions_per_owner() -> usize {
+    50
+}
+
+fn default_buffer_pressure_percent() -> u64 {
+    90
+}
+
+fn default_max_spoof_violations() -> u32 {
+    5
+}
+
+fn default_max_queued_events() -> usize {
+    512
+}
+
+fn default_max_queued_counters() -> usize {
+    1024
+}
+
+fn default_require_registered_rooms() -> bool {
+    false
+}
+
+fn default_user_cache_ttl_secs() -> u64 {
+    10800
+}
+
+fn default_stat_dump_interval() -> u64 {
+    420
+}
+
+fn default_stat_dump_dir() -> PathBuf {
+    PathBuf::from("conn-dumps")
+}
+
+fn default_bridge_breaker_threshold() -> usize {
+    10
+}
+
+fn default_bridge_breaker_window() -> u64 {
+    300
+}
+
+fn default_bridge_breaker_cooldown() -> u64 {
+    900
+}
+
+fn default_max_client_ingress_bandwidth() -> u64 {
+    1_048_576
+}
+
+fn default_max_client_egress_bandwidth() -> u64 {
+    8_388_608
+}
+
+fn default_reject_old_timestamps() -> bool {
+    false
+}
+
+fn default_status_print_interval() -> u64 {
+    if cfg!(debug_assertions) { 900 } else { 3600 }
+}
+
+fn default_max_message_bytes() -> usize {
+    1_048_576
+}
+
+fn default_disconnect_on_oversized_message() -> bool {
+    true
+}
+
+fn default_auth_timeout_secs() -> u64 {
+    15
+}
+
+fn default_snapshot_dir() -> PathBuf {
+    PathBuf::from("snapshots")
+}
+
+fn default_empty_session_grace_secs() -> u64 {
+    30
+}
+
+fn default_max_scripts_per_session() -> usize {
+    64
+}
+
+fn default_max_script_upload_bytes() -> usize {
+    512 * 1024
+}
+
+fn default_debug_packet_loss() -> f32 {
+    0.0
+}
+
+fn default_debug_added_latency_ms() -> u64 {
+    0
+}
+
+fn default_disable_ip_discovery() -> bool {
+    false
+}
+
+fn default_max_visible_players() -> usize {
+    100
+}
+
+fn default_voice_shedding_percent() -> u64 {
+    75
+}
+
+fn default_voice_shedding_max_ratio() -> f32 {
+    0.75
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, Validate)]
 #[serde(deny_unknown_fields)]
 pub struct Config {
     /// The memory usage value (1 to 11), determines how much memory the server will preallocate for operations.
-    #[serde(default = "default_memory_usage")]
+    /// The presets "low", "medium" and "high" can be used instead, and map to 1, 3 and 7 respectively.
+    #[serde(default = "default_memory_usage", deserialize_with = "deserialize_memory_usage")]
+    #[validate(range(min = 1, max = 11))]
     pub memory_usage: u32,
     /// How aggressive compression of data should be.
     /// 0 means no compression, 7 means prefer zstd almost always.
@@ -153,6 +320,10 @@ pub struct Config {
     /// If using QUIC to connect to the central server, this must be set to the path of the certificate file to use.
     #[serde(default)]
     pub quic_cert_path: Option<PathBuf>,
+    /// Local address that the connection to the central server should originate from, on hosts with multiple interfaces.
+    /// Not supported by the qunet client yet, the server refuses to start if this is set.
+    #[serde(default)]
+    pub bridge_bind_address: Option<String>,
 
     /// The name of the server that will be shown to clients.
     #[serde(default = "default_server_name")]
@@ -182,6 +353,7 @@ pub struct Config {
     /// The path to the QDB file.
     #[serde(default)]
     pub qdb_path: Option<PathBuf>,
+    /// Whether to track per-connection statistics, which are periodically dumped to `stat_dump_dir`.
     #[serde(default)]
     pub enable_stat_tracking: bool,
 
@@ -193,10 +365,175 @@ pub struct Config {
     pub tickrate: usize,
     #[serde(default = "default_verify_script_signatures")]
     pub verify_script_signatures: bool,
+    /// How long (in milliseconds) a player can go without sending an update before they are shown as culled to others,
+    /// instead of being frozen in place at their last known position. 0 disables this.
+    #[serde(default = "default_stale_player_timeout_ms")]
+    pub stale_player_timeout_ms: u64,
+    /// How many player metadata requests a single client can make per second. Requests over the limit are ignored
+    /// until the budget refills, clients will simply request the same data again later.
+    #[serde(default = "default_data_requests_per_second")]
+    #[validate(range(min = 1, max = 10000))]
+    pub data_requests_per_second: u64,
+    /// Maximum total size (in bytes) of all audio frames in a single voice message. Larger messages are rejected.
+    #[serde(default = "default_max_voice_message_size")]
+    #[validate(range(min = 1024, max = 1048576))]
+    pub max_voice_message_size: usize,
+    /// Maximum amount of sessions (levels being played) that can exist at once on this server.
+    /// Players trying to join a new session past this limit will be rejected. 0 means no limit.
+    #[serde(default = "default_max_sessions")]
+    pub max_sessions: usize,
+    /// Maximum amount of sessions that can exist at once in rooms owned by the same account.
+    /// Stops a single room owner from exhausting `max_sessions` by creating many rooms. 0 means no limit.
+    #[serde(default = "default_max_sessions_per_owner")]
+    pub max_sessions_per_owner: usize,
+    /// When heap usage of the buffer pool goes above this percentage of its maximum size (determined by `memory_usage`),
+    /// the server stops answering player metadata requests until the usage drops again. 0 disables this.
+    #[serde(default = "default_buffer_pressure_percent")]
+    #[validate(range(min = 0, max = 100))]
+    pub buffer_pressure_percent: u64,
+    /// How many times a client may send player data with someone else's account ID before being disconnected.
+    /// 0 means they are never disconnected, and the account ID is just corrected.
+    #[serde(default = "default_max_spoof_violations")]
+    pub max_spoof_violations: u32,
+    /// Maximum amount of events that can be queued for a single player, further events are dropped until the player receives them.
+    #[serde(default = "default_max_queued_events")]
+    #[validate(range(min = 16, max = 65536))]
+    pub max_queued_events: usize,
+    /// Maximum amount of pending counter (item ID) changes that can be queued for a single player, further changes are dropped.
+    #[serde(default = "default_max_queued_counters")]
+    #[validate(range(min = 16, max = 65536))]
+    pub max_queued_counters: usize,
+    /// If set, only these account IDs are allowed to log in to the server, useful for private testing servers.
+    /// An empty list or no list at all allows everyone.
+    #[serde(default)]
+    pub allowed_account_ids: Option<Vec<i32>>,
+    /// IP addresses or CIDR ranges (e.g. `1.2.3.4` or `10.0.0.0/8`) that are refused at connect time.
+    /// Reloading the config applies changes to this list without a restart.
+    #[serde(default)]
+    pub banned_ips: Vec<String>,
+    /// Type IDs of custom script events that clients are allowed to send, others are dropped.
+    /// An empty list allows all of them.
+    #[serde(default)]
+    pub allowed_scripted_events: Vec<u16>,
+    /// Address for the admin HTTP API to listen on (e.g. `127.0.0.1:4350`). Only used if the server was built with
+    /// the `admin-api` feature, and ignored unless `admin_token` is also set.
+    #[serde(default)]
+    pub admin_api_address: Option<String>,
+    /// Bearer token that must be passed in the `Authorization` header of every admin API request.
+    #[serde(default)]
+    pub admin_token: Option<String>,
+    /// Address (e.g. `127.0.0.1:4360`) of a monitoring service that receives player join/leave and session
+    /// lifecycle events as newline separated text over TCP. Requires a restart to change.
+    #[serde(default)]
+    pub observer_address: Option<String>,
+    /// Address (e.g. `0.0.0.0:4370`) for a health check endpoint, which answers HTTP requests with `ready` (200)
+    /// once the server is logged in to the central server, or `starting`/`degraded` (503) otherwise. Requires a restart to change.
+    #[serde(default)]
+    pub health_check_address: Option<String>,
+    /// If enabled, sessions can only be joined if their room was registered by the central server, including the global room (ID 0).
+    /// By default any session in the global room can be joined.
+    #[serde(default = "default_require_registered_rooms")]
+    pub require_registered_rooms: bool,
+    /// How long (in seconds) cached user data (e.g. muted state) is kept after it was last accessed.
+    /// Data of connected users is never evicted.
+    #[serde(default = "default_user_cache_ttl_secs")]
+    #[validate(range(min = 60))]
+    pub user_cache_ttl_secs: u64,
+    /// How often (in seconds) connection stats are dumped when stat tracking is enabled. 0 disables periodic dumps,
+    /// stats can still be dumped manually by sending SIGUSR1.
+    #[serde(default = "default_stat_dump_interval")]
+    pub stat_dump_interval: u64,
+    /// Directory that connection stat dumps are written to, relative paths are relative to the working directory.
+    #[serde(default = "default_stat_dump_dir")]
+    pub stat_dump_dir: PathBuf,
+    /// How many consecutive failed connection attempts to the central server (within `bridge_breaker_window`)
+    /// trip the circuit breaker, pausing reconnection for `bridge_breaker_cooldown` seconds. 0 disables the breaker.
+    #[serde(default = "default_bridge_breaker_threshold")]
+    pub bridge_breaker_threshold: usize,
+    /// Window (in seconds) in which failed connection attempts are counted towards the circuit breaker.
+    #[serde(default = "default_bridge_breaker_window")]
+    #[validate(range(min = 1))]
+    pub bridge_breaker_window: u64,
+    /// How long (in seconds) to wait before reconnecting to the central server once the circuit breaker trips.
+    #[serde(default = "default_bridge_breaker_cooldown")]
+    #[validate(range(min = 1))]
+    pub bridge_breaker_cooldown: u64,
+    /// Maximum amount of bytes per second a single client can send, messages from a client over the budget are dropped.
+    /// 0 disables the limit.
+    #[serde(default = "default_max_client_ingress_bandwidth")]
+    pub max_client_ingress_bandwidth: u64,
+    /// Maximum amount of bytes per second sent to a single client. While a client is over the budget, player data and
+    /// voice for it are skipped, events are still delivered. 0 disables the limit.
+    #[serde(default = "default_max_client_egress_bandwidth")]
+    pub max_client_egress_bandwidth: u64,
+    /// Whether to drop player updates with a timestamp older than the last accepted one, which can cause jitter
+    /// when other players interpolate. Some clients reset their timestamp on respawn, so this is disabled by default.
+    #[serde(default = "default_reject_old_timestamps")]
+    pub reject_old_timestamps: bool,
+    /// How often (in seconds) to print the server status to the log, 0 disables periodic status output.
+    /// Defaults to 15 minutes in debug builds and an hour in release builds.
+    #[serde(default = "default_status_print_interval")]
+    pub status_print_interval: u64,
+    /// Maximum size (in bytes) of a single message from a client, larger messages are rejected before being decoded.
+    #[serde(default = "default_max_message_bytes")]
+    #[validate(range(min = 65536))]
+    pub max_message_bytes: usize,
+    /// Whether to disconnect clients that send a message larger than `max_message_bytes`, instead of just ignoring it.
+    #[serde(default = "default_disconnect_on_oversized_message")]
+    pub disconnect_on_oversized_message: bool,
+    /// How long (in seconds) a client has to log in after connecting. Unauthenticated clients that send anything else
+    /// after this are disconnected. 0 disables the timeout.
+    #[serde(default = "default_auth_timeout_secs")]
+    pub auth_timeout_secs: u64,
+    /// Directory that session snapshots requested through the admin API are written to.
+    #[serde(default = "default_snapshot_dir")]
+    pub snapshot_dir: PathBuf,
+    /// How long (in seconds) an empty session is kept around before being deleted, so that players who briefly disconnect
+    /// and rejoin keep the session state (e.g. counters). 0 deletes sessions as soon as they become empty.
+    #[serde(default = "default_empty_session_grace_secs")]
+    pub empty_session_grace_secs: u64,
+    /// Maximum amount of scripts a session owner can upload at once. Cannot be higher than 64, which is the hard limit.
+    #[serde(default = "default_max_scripts_per_session")]
+    #[validate(range(min = 1, max = 64))]
+    pub max_scripts_per_session: usize,
+    /// Maximum combined size in bytes of all scripts in a single upload.
+    #[serde(default = "default_max_script_upload_bytes")]
+    #[validate(range(min = 1024))]
+    pub max_script_upload_bytes: usize,
+    /// Fraction (0.0 - 1.0) of unreliable player data packets to drop, for testing clients on bad networks.
+    /// Only used if the server was built with the `debug-netsim` feature.
+    #[serde(default = "default_debug_packet_loss")]
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub debug_packet_loss: f32,
+    /// Delay in milliseconds added to unreliable player data packets before they are sent.
+    /// Only used if the server was built with the `debug-netsim` feature.
+    #[serde(default = "default_debug_added_latency_ms")]
+    pub debug_added_latency_ms: u64,
+    /// If enabled and `server_address` is not set, the advertised address is taken from the UDP/TCP bind address instead of
+    /// looking up the public IP address online. Useful for LAN or airgapped setups, the bind address must not be a wildcard then.
+    #[serde(default = "default_disable_ip_discovery")]
+    pub disable_ip_discovery: bool,
+    /// Once a session has more players than this, the view distance of each player is shrunk so that roughly
+    /// this many players are visible to them at once. 0 disables the scaling.
+    #[serde(default = "default_max_visible_players")]
+    pub max_visible_players: usize,
+    /// When heap usage of the buffer pool goes above this percentage of its maximum size, the server starts dropping
+    /// voice messages instead of broadcasting them, more of them the closer it gets to the limit. 0 disables this.
+    #[serde(default = "default_voice_shedding_percent")]
+    #[validate(range(min = 0, max = 100))]
+    pub voice_shedding_percent: u64,
+    /// Fraction of voice messages dropped when the buffer pool is completely full, see `voice_shedding_percent`.
+    #[serde(default = "default_voice_shedding_max_ratio")]
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub voice_shedding_max_ratio: f32,
 
     /// Formula used to estimate server load, sent to the server and used to help clients choose a better server.
     #[serde(default = "default_server_load_formula")]
     pub server_load_formula: Option<String>,
+    /// How often (in seconds) to report server statistics to the central server.
+    #[serde(default = "default_status_report_interval")]
+    #[validate(range(min = 5, max = 3600))]
+    pub status_report_interval: u64,
 }
 
 impl Default for Config {
@@ -207,6 +544,7 @@ impl Default for Config {
             central_server_url: String::new(),
             central_server_password: String::new(),
             quic_cert_path: None,
+            bridge_bind_address: None,
             server_name: default_server_name(),
             server_id: default_server_id(),
             server_region: default_server_region(),
@@ -218,7 +556,48 @@ impl Default for Config {
             logging: default_logging(),
             tickrate: default_tickrate(),
             verify_script_signatures: default_verify_script_signatures(),
+            stale_player_timeout_ms: default_stale_player_timeout_ms(),
+            data_requests_per_second: default_data_requests_per_second(),
+            max_voice_message_size: default_max_voice_message_size(),
+            max_sessions: default_max_sessions(),
+            max_sessions_per_owner: default_max_sessions_per_owner(),
+            buffer_pressure_percent: default_buffer_pressure_percent(),
+            max_spoof_violations: default_max_spoof_violations(),
+            max_queued_events: default_max_queued_events(),
+            max_queued_counters: default_max_queued_counters(),
+            allowed_account_ids: None,
+            banned_ips: Vec::new(),
+            allowed_scripted_events: Vec::new(),
+            admin_api_address: None,
+            admin_token: None,
+            observer_address: None,
+            health_check_address: None,
+            require_registered_rooms: default_require_registered_rooms(),
+            user_cache_ttl_secs: default_user_cache_ttl_secs(),
+            stat_dump_interval: default_stat_dump_interval(),
+            stat_dump_dir: default_stat_dump_dir(),
+            bridge_breaker_threshold: default_bridge_breaker_threshold(),
+            bridge_breaker_window: default_bridge_breaker_window(),
+            bridge_breaker_cooldown: default_bridge_breaker_cooldown(),
+            max_client_ingress_bandwidth: default_max_client_ingress_bandwidth(),
+            max_client_egress_bandwidth: default_max_client_egress_bandwidth(),
+            reject_old_timestamps: default_reject_old_timestamps(),
+            status_print_interval: default_status_print_interval(),
+            max_message_bytes: default_max_message_bytes(),
+            disconnect_on_oversized_message: default_disconnect_on_oversized_message(),
+            auth_timeout_secs: default_auth_timeout_secs(),
+            snapshot_dir: default_snapshot_dir(),
+            empty_session_grace_secs: default_empty_session_grace_secs(),
+            max_scripts_per_session: default_max_scripts_per_session(),
+            max_script_upload_bytes: default_max_script_upload_bytes(),
+            debug_packet_loss: default_debug_packet_loss(),
+            debug_added_latency_ms: default_debug_added_latency_ms(),
+            disable_ip_discovery: default_disable_ip_discovery(),
+            max_visible_players: default_max_visible_players(),
+            voice_shedding_percent: default_voice_shedding_percent(),
+            voice_shedding_max_ratio: default_voice_shedding_max_ratio(),
             server_load_formula: default_server_load_formula(),
+            status_report_interval: default_status_report_interval(),
         }
     }
 }
@@ -262,12 +641,19 @@ impl Config {
     }
 
     fn replace_with_env(&mut self) {
-        env_replace("GLOBED_GS_MEMORY_USAGE", &mut self.memory_usage);
+        if let Ok(value) = std::env::var("GLOBED_GS_MEMORY_USAGE") {
+            if let Some(usage) = memory_usage_preset(&value) {
+                self.memory_usage = usage;
+            } else {
+                env_replace("GLOBED_GS_MEMORY_USAGE", &mut self.memory_usage);
+            }
+        }
         env_replace("GLOBED_GS_COMPRESSION_LEVEL", &mut self.compression_level);
 
         env_replace("GLOBED_GS_CENTRAL_URL", &mut self.central_server_url);
         env_replace("GLOBED_GS_CENTRAL_PASSWORD", &mut self.central_server_password);
         env_replace("GLOBED_GS_QUIC_CERT_PATH", &mut self.quic_cert_path);
+        env_replace("GLOBED_GS_BRIDGE_BIND_ADDRESS", &mut self.bridge_bind_address);
 
         env_replace("GLOBED_GS_SERVER_NAME", &mut self.server_name);
         env_replace("GLOBED_GS_SERVER_ID", &mut self.server_id);
@@ -290,8 +676,52 @@ impl Config {
         env_replace("GLOBED_GS_LOG_ROLLING", &mut self.logging.rolling);
 
         env_replace("GLOBED_GS_QDB_PATH", &mut self.qdb_path);
+        env_replace("GLOBED_GS_ADMIN_API_ADDRESS", &mut self.admin_api_address);
+        env_replace("GLOBED_GS_ADMIN_TOKEN", &mut self.admin_token);
+        env_replace("GLOBED_GS_OBSERVER_ADDRESS", &mut self.observer_address);
+        env_replace("GLOBED_GS_HEALTH_CHECK_ADDRESS", &mut self.health_check_address);
         env_replace("GLOBED_GS_ENABLE_STAT_TRACKING", &mut self.enable_stat_tracking);
 
         env_replace("GLOBED_GS_TICKRATE", &mut self.tickrate);
+        env_replace("GLOBED_GS_STALE_PLAYER_TIMEOUT_MS", &mut self.stale_player_timeout_ms);
+        env_replace("GLOBED_GS_DATA_REQUESTS_PER_SECOND", &mut self.data_requests_per_second);
+        env_replace("GLOBED_GS_MAX_VOICE_MESSAGE_SIZE", &mut self.max_voice_message_size);
+        env_replace("GLOBED_GS_MAX_SESSIONS", &mut self.max_sessions);
+        env_replace("GLOBED_GS_MAX_SESSIONS_PER_OWNER", &mut self.max_sessions_per_owner);
+        env_replace("GLOBED_GS_BUFFER_PRESSURE_PERCENT", &mut self.buffer_pressure_percent);
+        env_replace("GLOBED_GS_MAX_SPOOF_VIOLATIONS", &mut self.max_spoof_violations);
+        env_replace("GLOBED_GS_MAX_QUEUED_EVENTS", &mut self.max_queued_events);
+        env_replace("GLOBED_GS_MAX_QUEUED_COUNTERS", &mut self.max_queued_counters);
+        env_replace("GLOBED_GS_REQUIRE_REGISTERED_ROOMS", &mut self.require_registered_rooms);
+        env_replace("GLOBED_GS_USER_CACHE_TTL_SECS", &mut self.user_cache_ttl_secs);
+        env_replace("GLOBED_GS_STAT_DUMP_INTERVAL", &mut self.stat_dump_interval);
+        env_replace("GLOBED_GS_STAT_DUMP_DIR", &mut self.stat_dump_dir);
+        env_replace("GLOBED_GS_BRIDGE_BREAKER_THRESHOLD", &mut self.bridge_breaker_threshold);
+        env_replace("GLOBED_GS_BRIDGE_BREAKER_WINDOW", &mut self.bridge_breaker_window);
+        env_replace("GLOBED_GS_BRIDGE_BREAKER_COOLDOWN", &mut self.bridge_breaker_cooldown);
+        env_replace(
+            "GLOBED_GS_MAX_CLIENT_INGRESS_BANDWIDTH",
+            &mut self.max_client_ingress_bandwidth,
+        );
+        env_replace("GLOBED_GS_MAX_CLIENT_EGRESS_BANDWIDTH", &mut self.max_client_egress_bandwidth);
+        env_replace("GLOBED_GS_REJECT_OLD_TIMESTAMPS", &mut self.reject_old_timestamps);
+        env_replace("GLOBED_GS_STATUS_PRINT_INTERVAL", &mut self.status_print_interval);
+        env_replace("GLOBED_GS_MAX_MESSAGE_BYTES", &mut self.max_message_bytes);
+        env_replace(
+            "GLOBED_GS_DISCONNECT_ON_OVERSIZED_MESSAGE",
+            &mut self.disconnect_on_oversized_message,
+        );
+        env_replace("GLOBED_GS_AUTH_TIMEOUT_SECS", &mut self.auth_timeout_secs);
+        env_replace("GLOBED_GS_SNAPSHOT_DIR", &mut self.snapshot_dir);
+        env_replace("GLOBED_GS_EMPTY_SESSION_GRACE_SECS", &mut self.empty_session_grace_secs);
+        env_replace("GLOBED_GS_MAX_SCRIPTS_PER_SESSION", &mut self.max_scripts_per_session);
+        env_replace("GLOBED_GS_MAX_SCRIPT_UPLOAD_BYTES", &mut self.max_script_upload_bytes);
+        env_replace("GLOBED_GS_DEBUG_PACKET_LOSS", &mut self.debug_packet_loss);
+        env_replace("GLOBED_GS_DEBUG_ADDED_LATENCY_MS", &mut self.debug_added_latency_ms);
+        env_replace("GLOBED_GS_DISABLE_IP_DISCOVERY", &mut self.disable_ip_discovery);
+        env_replace("GLOBED_GS_MAX_VISIBLE_PLAYERS", &mut self.max_visible_players);
+        env_replace("GLOBED_GS_VOICE_SHEDDING_PERCENT", &mut self.voice_shedding_percent);
+        env_replace("GLOBED_GS_VOICE_SHEDDING_MAX_RATIO", &mut self.voice_shedding_max_ratio);
+        env_replace("GLOBED_GS_STATUS_REPORT_INTERVAL", &mut self.status_report_interval);
     }
 }
@@ -1,4 +1,9 @@
-use server_shared::{events::EventEncode, qunet::buffers::HeapByteWriter};
+use server_shared::{
+    encoding::DataDecodeError,
+    events::EventEncode,
+    qunet::buffers::{ByteReader, HeapByteWriter},
+};
+use smallvec::SmallVec;
 
 pub struct DisplayDataRefreshedEvent {
     pub player: i32,
@@ -17,3 +22,303 @@ impl EventEncode for DisplayDataRefreshedEvent {
         writer.write_i32(self.player);
     }
 }
+
+pub struct SessionOwnerChangedEvent {
+    pub owner: i32,
+}
+
+impl EventEncode for SessionOwnerChangedEvent {
+    fn size_bound(&self) -> Option<usize> {
+        Some(4)
+    }
+
+    fn id() -> &'static str {
+        "globed/session.owner-changed"
+    }
+
+    fn encode(&self, writer: &mut HeapByteWriter) {
+        writer.write_i32(self.owner);
+    }
+}
+
+/// Sent by the server on behalf of a 2-player mode partner that left the session.
+/// The partner is passed in `EventOptions::sent_by_player`, like for unlinks sent by clients.
+pub struct TwoPlayerUnlinkEvent;
+
+impl EventEncode for TwoPlayerUnlinkEvent {
+    fn size_bound(&self) -> Option<usize> {
+        Some(0)
+    }
+
+    fn id() -> &'static str {
+        "globed/2p.unlink"
+    }
+
+    fn encode(&self, _writer: &mut HeapByteWriter) {}
+}
+
+/// Sent by the client after joining a session to pick how its player data is delivered.
+///
+/// Reliable delivery means lost packets are retransmitted instead of being superseded by the next tick,
+/// which costs extra bandwidth (and latency on lossy connections) for every position update.
+pub struct PlayerDataOptionsEvent {
+    pub reliable: bool,
+}
+
+impl PlayerDataOptionsEvent {
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        Ok(Self { reliable: reader.read_bool()? })
+    }
+}
+
+/// Sent by the client to toggle whether it's hidden from other (non-moderator) players in the session
+pub struct SetHiddenEvent {
+    pub hidden: bool,
+}
+
+impl SetHiddenEvent {
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        Ok(Self { hidden: reader.read_bool()? })
+    }
+}
+
+/// Sent by a moderator to freeze or unfreeze another player
+pub struct FreezeUserEvent {
+    pub account_id: i32,
+    pub frozen: bool,
+}
+
+impl FreezeUserEvent {
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        Ok(Self {
+            account_id: reader.read_i32()?,
+            frozen: reader.read_bool()?,
+        })
+    }
+}
+
+/// Sent by the session owner to change the tickrate of the session, and by the server to everyone once it changed
+pub struct SessionTickrateEvent {
+    pub tickrate: u16,
+}
+
+impl SessionTickrateEvent {
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        Ok(Self { tickrate: reader.read_u16()? })
+    }
+}
+
+impl EventEncode for SessionTickrateEvent {
+    fn size_bound(&self) -> Option<usize> {
+        Some(2)
+    }
+
+    fn id() -> &'static str {
+        "globed/session.tickrate"
+    }
+
+    fn encode(&self, writer: &mut HeapByteWriter) {
+        writer.write_u16(self.tickrate);
+    }
+}
+
+/// Sent by the session owner to pause or resume the session, and by the server to everyone once it changed
+pub struct SessionPauseEvent {
+    pub paused: bool,
+}
+
+impl SessionPauseEvent {
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        Ok(Self { paused: reader.read_bool()? })
+    }
+}
+
+impl EventEncode for SessionPauseEvent {
+    fn size_bound(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn id() -> &'static str {
+        "globed/session.pause"
+    }
+
+    fn encode(&self, writer: &mut HeapByteWriter) {
+        writer.write_bool(self.paused);
+    }
+}
+
+/// Maximum size of the data the session owner can attach to a session
+pub const MAX_SESSION_BLOB_SIZE: usize = 4096;
+
+/// Sent to players when they join a session with attached data, and to everyone once it changes.
+/// Clients that already have data with this hash can skip requesting it again.
+pub struct SessionBlobInfoEvent {
+    pub version: u32,
+    pub size: u32,
+    pub hash: [u8; 32],
+}
+
+impl EventEncode for SessionBlobInfoEvent {
+    fn size_bound(&self) -> Option<usize> {
+        Some(40)
+    }
+
+    fn id() -> &'static str {
+        "globed/session.blob-info"
+    }
+
+    fn encode(&self, writer: &mut HeapByteWriter) {
+        writer.write_u32(self.version);
+        writer.write_u32(self.size);
+        writer.write_bytes(&self.hash);
+    }
+}
+
+/// Sent in response to `globed/session.request-blob`
+pub struct SessionBlobEvent<'a> {
+    pub version: u32,
+    pub data: &'a [u8],
+}
+
+impl EventEncode for SessionBlobEvent<'_> {
+    fn size_bound(&self) -> Option<usize> {
+        Some(4 + self.data.len())
+    }
+
+    fn id() -> &'static str {
+        "globed/session.blob"
+    }
+
+    fn encode(&self, writer: &mut HeapByteWriter) {
+        writer.write_u32(self.version);
+        writer.write_bytes(self.data);
+    }
+}
+
+/// Sent by the client to switch to a different voice channel, 0 is the global channel
+pub struct SetVoiceChannelEvent {
+    pub channel: u16,
+}
+
+impl SetVoiceChannelEvent {
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        Ok(Self { channel: reader.read_u16()? })
+    }
+}
+
+/// Maximum length of a chat message in bytes
+pub const MAX_CHAT_MESSAGE_LEN: usize = 256;
+
+/// Text chat message. Clients send just the text, the server attaches the sender when relaying it.
+pub struct ChatMessageEvent {
+    pub sender: i32,
+    pub text: String,
+}
+
+impl ChatMessageEvent {
+    pub fn decode(sender: i32, data: &[u8]) -> Result<Self, DataDecodeError> {
+        if data.is_empty() || data.len() > MAX_CHAT_MESSAGE_LEN {
+            return Err(DataDecodeError::ValidationFailed);
+        }
+
+        let text = std::str::from_utf8(data).map_err(|_| DataDecodeError::ValidationFailed)?;
+
+        Ok(Self { sender, text: text.to_owned() })
+    }
+}
+
+impl EventEncode for ChatMessageEvent {
+    fn size_bound(&self) -> Option<usize> {
+        Some(4 + self.text.len())
+    }
+
+    fn id() -> &'static str {
+        "globed/chat.message"
+    }
+
+    fn encode(&self, writer: &mut HeapByteWriter) {
+        writer.write_i32(self.sender);
+        writer.write_bytes(self.text.as_bytes());
+    }
+}
+
+/// Announcement from the server administrators, shown to every player in a session
+pub struct AnnouncementEvent<'a> {
+    pub text: &'a str,
+}
+
+impl EventEncode for AnnouncementEvent<'_> {
+    fn size_bound(&self) -> Option<usize> {
+        Some(self.text.len())
+    }
+
+    fn id() -> &'static str {
+        "globed/announcement"
+    }
+
+    fn encode(&self, writer: &mut HeapByteWriter) {
+        writer.write_bytes(self.text.as_bytes());
+    }
+}
+
+/// Maximum amount of players included in a radar update, the closest ones are picked
+pub const RADAR_MAX_PLAYERS: usize = 16;
+/// Players further away than this from the camera center are left out of radar updates
+pub const RADAR_MAX_DISTANCE: f32 = 3000.0;
+
+/// Sent by the client to opt into (or out of) radar updates in classic levels
+pub struct RadarOptionsEvent {
+    pub enabled: bool,
+}
+
+impl RadarOptionsEvent {
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        Ok(Self { enabled: reader.read_bool()? })
+    }
+}
+
+pub struct RadarEntry {
+    pub account_id: i32,
+    /// Angle from the camera center to the player in radians, see `Point::angle_to`
+    pub angle: f32,
+    pub distance: f32,
+}
+
+/// Bearings to the closest players, sent alongside player data to clients that enabled the radar
+pub struct RadarEvent {
+    pub entries: SmallVec<[RadarEntry; RADAR_MAX_PLAYERS]>,
+}
+
+impl EventEncode for RadarEvent {
+    fn size_bound(&self) -> Option<usize> {
+        Some(1 + self.entries.len() * 12)
+    }
+
+    fn id() -> &'static str {
+        "globed/radar"
+    }
+
+    fn encode(&self, writer: &mut HeapByteWriter) {
+        writer.write_u8(self.entries.len() as u8);
+
+        for entry in &self.entries {
+            writer.write_i32(entry.account_id);
+            writer.write_f32(entry.angle);
+            writer.write_f32(entry.distance);
+        }
+    }
+}
@@ -6,6 +6,21 @@ use server_shared::{
 };
 use smallvec::SmallVec;
 
+// Custom script event
+
+/// Event defined by a level script, only the type is read here, the data after it is relayed untouched
+pub struct CustomScriptEvent {
+    pub event_type: u16,
+}
+
+impl CustomScriptEvent {
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        Ok(Self { event_type: reader.read_u16()? })
+    }
+}
+
 // Counter change
 
 #[derive(Clone)]
@@ -16,6 +31,9 @@ pub enum CounterChangeType {
     Divide(f32),
 }
 
+/// Item IDs are packed into 24 bits on the wire, the top byte holds the change type
+pub const COUNTER_ITEM_ID_MASK: u32 = 0x00ffffff;
+
 #[derive(Clone)]
 pub struct CounterChangeEvent {
     pub item_id: u32,
@@ -29,7 +47,7 @@ impl CounterChangeEvent {
         let raw_data = reader.read_u64()?;
 
         let raw_type = (raw_data >> 56) as u8;
-        let item_id = ((raw_data >> 32) as u32) & 0x00ffffff;
+        let item_id = ((raw_data >> 32) as u32) & COUNTER_ITEM_ID_MASK;
         let raw_value = raw_data as u32;
 
         let r#type = match raw_type {
@@ -61,7 +79,7 @@ impl EventEncode for CounterChangeEvent {
             CounterChangeType::Divide(_) => 3,
         };
 
-        let item_id = (self.item_id as u64) & 0x00ffffff;
+        let item_id = (self.item_id & COUNTER_ITEM_ID_MASK) as u64;
         let value = match self.r#type {
             CounterChangeType::Set(val) => val as u64,
             CounterChangeType::Add(val) => val as u64,
@@ -77,15 +95,25 @@ impl EventEncode for CounterChangeEvent {
 
 // Spawn
 
+/// Maximum amount of group remaps in a spawn, the count is encoded as a single byte
+pub const MAX_SPAWN_REMAP_PAIRS: usize = 255;
+
 #[derive(Default, Clone)]
 pub struct SpawnInfo {
     pub group_id: u16,
     pub delay: f32,
     pub delay_variance: f32,
     pub ordered: bool,
+    /// Flattened pairs of (original group, new group), so the length is always even.
     pub remaps: SmallVec<[u16; 6]>,
 }
 
+impl SpawnInfo {
+    pub fn is_valid(&self) -> bool {
+        self.remaps.len().is_multiple_of(2) && self.remaps.len() / 2 <= MAX_SPAWN_REMAP_PAIRS
+    }
+}
+
 #[bitpiece]
 #[derive(Default)]
 struct SpawnInfoFlags {
@@ -99,11 +127,42 @@ pub struct SpawnGroupEvent(pub SpawnInfo);
 
 impl SpawnGroupEvent {
     pub fn new(info: SpawnInfo) -> Option<Self> {
-        if info.remaps.len() > 510 || !info.remaps.len().is_multiple_of(2) {
-            return None;
+        info.is_valid().then_some(Self(info))
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        let flags = SpawnInfoFlags::from_bits(reader.read_u8()?);
+        let group_id =
+            u16::try_from(reader.read_varuint()?).map_err(|_| DataDecodeError::ValidationFailed)?;
+
+        let mut info = SpawnInfo {
+            group_id,
+            ordered: flags.ordered(),
+            ..Default::default()
+        };
+
+        if flags.has_delay() {
+            info.delay = reader.read_f32()?;
+
+            if flags.has_delay_variance() {
+                info.delay_variance = reader.read_f32()?;
+            }
         }
 
-        Some(Self(info))
+        if flags.has_remaps() {
+            // the count is in pairs, so an odd amount of remaps can't be represented
+            let pairs = reader.read_u8()? as usize;
+
+            for _ in 0..pairs * 2 {
+                let key = u16::try_from(reader.read_varuint()?)
+                    .map_err(|_| DataDecodeError::ValidationFailed)?;
+                info.remaps.push(key);
+            }
+        }
+
+        Self::new(info).ok_or(DataDecodeError::ValidationFailed)
     }
 }
 
@@ -195,6 +254,43 @@ impl CustomScriptEvent {
     }
 }
 
+// Script logs
+
+/// Sent by the session owner to start or stop receiving script logs as they are produced
+#[cfg(feature = "scripting")]
+pub struct ScriptLogStreamEvent {
+    pub enabled: bool,
+}
+
+#[cfg(feature = "scripting")]
+impl ScriptLogStreamEvent {
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        Ok(Self { enabled: reader.read_bool()? })
+    }
+}
+
+#[cfg(feature = "scripting")]
+pub struct ScriptLogLineEvent<'a> {
+    pub line: &'a str,
+}
+
+#[cfg(feature = "scripting")]
+impl EventEncode for ScriptLogLineEvent<'_> {
+    fn size_bound(&self) -> Option<usize> {
+        Some(self.line.len())
+    }
+
+    fn id() -> &'static str {
+        "globed/scripting.log-line"
+    }
+
+    fn encode(&self, writer: &mut HeapByteWriter) {
+        writer.write_bytes(self.line.as_bytes());
+    }
+}
+
 // Other scripting events
 
 pub struct SetItemEvent {
@@ -269,6 +365,21 @@ pub struct FollowPlayerEvent {
     pub enable: bool,
 }
 
+impl FollowPlayerEvent {
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        let raw_group = reader.read_u16()?;
+        let player_id = reader.read_i32()?;
+
+        Ok(Self {
+            player_id,
+            group: raw_group & !(1 << 15),
+            enable: raw_group & (1 << 15) != 0,
+        })
+    }
+}
+
 impl EventEncode for FollowPlayerEvent {
     fn size_bound(&self) -> Option<usize> {
         Some(6)
@@ -301,6 +412,23 @@ pub struct FollowRotationEvent {
     pub enable: bool,
 }
 
+impl FollowRotationEvent {
+    pub fn decode(data: &[u8]) -> Result<Self, DataDecodeError> {
+        let mut reader = ByteReader::new(data);
+
+        let raw_group = reader.read_u16()?;
+        let center = reader.read_u16()?;
+        let player_id = reader.read_i32()?;
+
+        Ok(Self {
+            player_id,
+            group: raw_group & !(1 << 15),
+            center,
+            enable: raw_group & (1 << 15) != 0,
+        })
+    }
+}
+
 impl EventEncode for FollowRotationEvent {
     fn size_bound(&self) -> Option<usize> {
         Some(8)
@@ -326,3 +454,87 @@ impl EventEncode for FollowRotationEvent {
         writer.write_i32(self.player_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode<E: EventEncode>(event: &E) -> Vec<u8> {
+        let mut writer = HeapByteWriter::new();
+        event.encode(&mut writer);
+        writer.written().to_vec()
+    }
+
+    #[test]
+    fn follow_player_enable_round_trip() {
+        let data = encode(&FollowPlayerEvent {
+            player_id: 1234,
+            group: 77,
+            enable: true,
+        });
+        let decoded = FollowPlayerEvent::decode(&data).unwrap();
+
+        assert_eq!(decoded.player_id, 1234);
+        assert_eq!(decoded.group, 77);
+        assert!(decoded.enable);
+    }
+
+    #[test]
+    fn follow_player_disable_round_trip() {
+        let data = encode(&FollowPlayerEvent {
+            player_id: 1234,
+            group: 77,
+            enable: false,
+        });
+        let decoded = FollowPlayerEvent::decode(&data).unwrap();
+
+        assert_eq!(decoded.player_id, 1234);
+        assert_eq!(decoded.group, 77);
+        assert!(!decoded.enable);
+    }
+
+    #[test]
+    fn follow_rotation_round_trip() {
+        let mut writer = HeapByteWriter::new();
+        writer.write_u16(42 | (1 << 15));
+        writer.write_u16(9);
+        writer.write_i32(-5);
+        let data = writer.written().to_vec();
+
+        let decoded = FollowRotationEvent::decode(&data).unwrap();
+
+        assert_eq!(decoded.player_id, -5);
+        assert_eq!(decoded.group, 42);
+        assert_eq!(decoded.center, 9);
+        assert!(decoded.enable);
+        assert_eq!(encode(&decoded), data);
+    }
+
+    fn spawn_with_remaps(remaps: &[u16]) -> SpawnInfo {
+        SpawnInfo {
+            group_id: 10,
+            remaps: remaps.iter().copied().collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn spawn_group_even_remaps_decode() {
+        let data = encode(&SpawnGroupEvent(spawn_with_remaps(&[1, 2, 3, 4])));
+        let decoded = SpawnGroupEvent::decode(&data).unwrap();
+
+        assert_eq!(decoded.0.group_id, 10);
+        assert_eq!(decoded.0.remaps.as_slice(), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn spawn_group_odd_remaps_rejected() {
+        assert!(SpawnGroupEvent::new(spawn_with_remaps(&[1, 2, 3])).is_none());
+
+        // two pairs announced, but the last remap is cut off, leaving three values
+        let mut data = encode(&SpawnGroupEvent(spawn_with_remaps(&[1, 2, 3, 4])));
+        data.pop();
+
+        assert!(SpawnGroupEvent::decode(&data).is_err());
+    }
+}
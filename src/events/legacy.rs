@@ -120,12 +120,16 @@ impl LegacyEventEncoder {
         events: &[OwnedEvent],
         writer: &mut impl Write,
     ) -> Result<(), EventEncodingError> {
-        let mut writer = BinaryWriter::new(writer);
-
         for event in events {
-            if let Err(e) = self.encode_event(&event.id, &event.data, &event.options, &mut writer) {
-                debug!("legacy writer failed to encode event {}: {e}", event.id);
+            // unknown events are the only thing that can fail after the type was already written,
+            // so skip them upfront instead of leaving partial bytes behind that would desync the client
+            if !self.knows_event(&event.id) {
+                debug!("legacy writer cannot encode event {}, skipping", event.id);
+                continue;
             }
+
+            // running out of space in the output is not recoverable, the caller has to drop the whole buffer
+            self.encode_event(&event.id, &event.data, &event.options, writer)?;
         }
 
         Ok(())
@@ -258,3 +262,36 @@ fn length_for_legacy_event(id: u16, data: &[u8]) -> Option<usize> {
         _ => return None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(cache: &EventStringCache, id: &str, data: &[u8]) -> OwnedEvent {
+        OwnedEvent {
+            id: cache.get(id),
+            data: data.to_vec(),
+            options: EventOptions::default(),
+        }
+    }
+
+    #[test]
+    fn unknown_event_mid_array_is_skipped() {
+        let cache = EventStringCache::new();
+        let encoder = LegacyEventEncoder::create(&cache);
+
+        let first = event(&cache, "globed/counter-change", &[1, 2, 3, 4, 5, 6, 7, 8]);
+        let unknown = event(&cache, "globed/not-a-legacy-event", &[9, 9, 9]);
+        let last = event(&cache, "globed/display-data-refreshed", &[]);
+
+        let mut expected = Vec::new();
+        for ev in [&first, &last] {
+            encoder.encode_event(&ev.id, &ev.data, &ev.options, &mut expected).unwrap();
+        }
+
+        let mut out = Vec::new();
+        encoder.encode_events(&[first, unknown, last], &mut out).unwrap();
+
+        assert_eq!(out, expected);
+    }
+}
@@ -5,7 +5,7 @@ use std::{
     path::Path,
     sync::{
         Arc, OnceLock, Weak,
-        atomic::{AtomicU64, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
     },
     time::{Duration, Instant, SystemTime},
 };
@@ -40,17 +40,26 @@ use thiserror::Error;
 use tracing::{debug, error, info, trace, warn};
 
 use crate::{
-    bridge::{Bridge, ServerRole},
-    client_data::ClientData,
+    bridge::{Bridge, RolePermissions, ServerRole},
+    client_data::{
+        BandwidthState, ClientData, ConnectionKind, MAX_NAME_COLOR_SIZE, SpecialUserData,
+        TrafficDirection,
+    },
     client_store::ClientStore,
     config::Config,
     data,
     events::EventEncoder,
     events::*,
+    health::HealthState,
+    ip_ban::IpBanList,
     load_calculator::LoadCalculator,
-    player_state::{CameraRange, PlayerLevelMeta, PlayerState},
-    session_manager::{GameSession, SessionManager},
-    voice_message::VoiceMessage,
+    observer::{ObserverEvent, ObserverSink, TcpObserverSink},
+    player_state::{CameraRange, PlayerLevelMeta, PlayerState, Point},
+    session_manager::{
+        GameSession, LinkRequestOutcome, QueueLimits, SESSION_LIFETIME_BUCKETS, SessionManager,
+        TickSnapshot,
+    },
+    voice_message::{VoiceError, VoiceMessage},
 };
 
 struct CentralRoom {
@@ -82,20 +91,52 @@ pub struct ConnectionHandler {
     legacy_event_encoder: Arc<LegacyEventEncoder>,
 
     config: ArcSwap<Config>,
+    /// Built from `Config::allowed_account_ids`, `None` means everyone is allowed
+    allowed_accounts: ArcSwap<Option<HashSet<i32>>>,
+    banned_ips: ArcSwap<IpBanList>,
 
     total_connections: AtomicU64,
     total_data_messages: AtomicU64,
+    /// Highest number of authorized clients at once, since launch and since the last status report
+    peak_clients: AtomicUsize,
+    interval_peak_clients: AtomicUsize,
+    /// Number of received messages of each type, keyed by the message name
+    message_counts: DashMap<&'static str, AtomicU64>,
+    /// Connection IDs of clients that have not logged in yet, and when they connected
+    pending_logins: DashMap<u64, Instant>,
+    /// Currently open connections, indexed by `ConnectionKind`
+    connections_by_kind: [AtomicUsize; ConnectionKind::COUNT],
 
     load_calculator: Option<Mutex<LoadCalculator>>,
     cached_status_data: Mutex<SrvStatusData>,
     cached_load: AtomicF32,
+
+    /// Moving average of the encoded size of a single player in level data,
+    /// used to size buffers in `handle_player_data`. 0 until the first sample.
+    player_size_estimate: AtomicF32,
+
+    /// Set when the buffer pool is close to its memory limit, and non-essential work should be skipped
+    under_buffer_pressure: AtomicBool,
+    /// Fraction of voice messages to drop, raised as the buffer pool fills up
+    voice_drop_ratio: AtomicF32,
+    dropped_voice_messages: AtomicU64,
+    /// Whether new UDP connections are accepted, when disabled UDP is only used for discovery pings
+    udp_connections_enabled: AtomicBool,
+    #[cfg(feature = "voice-processing")]
+    voice_processor: Box<dyn crate::voice_processor::VoiceProcessor>,
+
+    started_at: Instant,
 }
 
 pub type ClientStateHandle = Arc<ClientState<ConnectionHandler>>;
 pub type WeakClientStateHandle = Weak<ClientState<ConnectionHandler>>;
 
 const MAX_SCRIPT_COUNT: usize = 64;
+#[cfg_attr(not(feature = "scripting"), allow(unused))]
+const MAX_SCRIPT_FILENAME_LEN: usize = 64;
 pub const MAX_EVENT_COUNT: usize = 64;
+/// Lowest tickrate a session owner can pick for their session
+const MIN_SESSION_TICKRATE: usize = 5;
 
 #[derive(Debug, Error)]
 pub enum HandlerError {
@@ -109,10 +150,39 @@ pub enum HandlerError {
     EventRateLimit,
     #[error("Failed to decode: {0}")]
     Decode(#[from] DataDecodeError),
+    #[error("Voice error: {0}")]
+    Voice(#[from] VoiceError),
 }
 
 type HandlerResult<T> = Result<T, HandlerError>;
 
+/// Reasons for rejecting a script upload as a whole, before anything is executed
+#[cfg_attr(not(feature = "scripting"), allow(unused))]
+#[derive(Debug, Error)]
+enum ScriptRejectReason {
+    #[error("too many scripts ({0}, the limit is {1})")]
+    TooManyScripts(usize, usize),
+    #[error("scripts are too large ({0} bytes, the limit is {1})")]
+    TooLarge(usize, usize),
+    #[error("no main script")]
+    NoMainScript,
+    #[error("{0} scripts are marked as main, only one is allowed")]
+    MultipleMainScripts(usize),
+    #[error("script has an empty filename")]
+    EmptyFilename,
+    #[error("filename is longer than {} characters", MAX_SCRIPT_FILENAME_LEN)]
+    FilenameTooLong,
+    #[error("invalid filename '{0}', only letters, digits, '_', '-' and '.' are allowed")]
+    InvalidFilename(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ChatKind {
+    Voice,
+    Quick,
+    Text,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum CanTalkOutcome {
     Allowed,
@@ -123,6 +193,24 @@ enum CanTalkOutcome {
     Disallowed,
 }
 
+/// Who performed a moderation action, for the audit log
+#[derive(Clone, Copy, Debug)]
+pub enum AuditActor {
+    Player(i32),
+    Admin,
+    CentralServer,
+}
+
+impl std::fmt::Display for AuditActor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Player(id) => write!(f, "player:{id}"),
+            Self::Admin => f.write_str("admin"),
+            Self::CentralServer => f.write_str("central"),
+        }
+    }
+}
+
 pub struct BorrowedLevelScript<'a> {
     pub content: &'a str,
     pub filename: &'a str,
@@ -145,8 +233,9 @@ impl AppHandler for ConnectionHandler {
         }
 
         info!(
-            "Globed game server is running! Build date: {}",
-            build_time_utc!("%Y-%m-%dT%H:%M:%S")
+            "Globed game server v{} is running! Build date: {}",
+            Self::version(),
+            Self::build_date()
         );
         info!(
             "- Server name: {} ({}), region: {}",
@@ -155,34 +244,57 @@ impl AppHandler for ConnectionHandler {
         info!("- Accepting connections on: {}", self.data.address);
         info!("- Central server: {}", self.bridge.server_url());
 
-        let status_intv = if cfg!(debug_assertions) {
-            Duration::from_mins(15)
-        } else {
-            Duration::from_mins(60)
-        };
+        #[cfg(feature = "debug-netsim")]
+        warn!(
+            "Network simulation is compiled in, player data may be dropped or delayed on purpose!"
+        );
+
+        #[cfg(feature = "admin-api")]
+        self.start_admin_api(&server);
+
+        self.start_health_check(&server);
 
-        server.schedule(status_intv, |server| async move {
-            server.print_server_status();
+        let status_intv = self.config.load().status_print_interval;
+        // routine cleanup runs on the same schedule, so keep it hourly if status output is disabled
+        let routine_intv = if status_intv == 0 { 3600 } else { status_intv };
+
+        server.schedule(Duration::from_secs(routine_intv), move |server| async move {
+            if status_intv != 0 {
+                server.print_server_status();
+                server.handler().print_status();
+            }
 
             // do some routine cleanup
             #[cfg(feature = "scripting")]
             crate::scripting::run_cleanup();
         });
 
-        server.schedule(Duration::from_hours(6), |server| async move {
-            // TODO: determine if this is really worth it?
-            // let pool = server.get_buffer_pool();
-            // let prev_usage = pool.stats().total_heap_usage;
-            // pool.shrink();
-            // let new_usage = pool.stats().total_heap_usage;
-
-            // info!("Shrinking buffer pool to reclaim memory: {} -> {} bytes", prev_usage, new_usage);
+        // TODO: shrink the buffer pool every few hours to reclaim memory, if it's really worth it?
 
+        server.schedule(Duration::from_mins(15), |server| async move {
             server.handler().cleanup_user_data_cache();
         });
 
-        if server.stat_tracker().is_some() {
-            server.schedule(Duration::from_mins(7), |server| async move {
+        server.schedule(Duration::from_secs(1), |server| async move {
+            server.handler().check_buffer_pressure();
+        });
+
+        server.schedule(Duration::from_mins(10), |server| async move {
+            server.handler().vacuum_clients();
+        });
+
+        server.schedule(Duration::from_secs(5), |server| async move {
+            server.handler().session_manager.sweep_empty_sessions();
+        });
+
+        server.schedule(Duration::from_secs(5), |server| async move {
+            server.handler().sweep_login_timeouts();
+        });
+
+        let stat_dump_interval = self.config.load().stat_dump_interval;
+
+        if server.stat_tracker().is_some() && stat_dump_interval > 0 {
+            server.schedule(Duration::from_secs(stat_dump_interval), |server| async move {
                 server.handler().dump_all_connections().await;
             });
         }
@@ -201,12 +313,39 @@ impl AppHandler for ConnectionHandler {
             return Err("server not initialized yet".into());
         }
 
+        let bans = self.banned_ips.load();
+        if !bans.is_empty() && bans.contains(address.ip()) {
+            debug!("Refusing connection from banned address {}", address);
+            return Err("address is banned".into());
+        }
+
         info!(
             "Client connected: connection_id={}, address={}, kind={}",
             connection_id, address, kind
         );
 
-        Ok(ClientData::default())
+        let kind = ConnectionKind::parse(kind);
+
+        if kind == ConnectionKind::Udp && !self.udp_connections_enabled.load(Ordering::Relaxed) {
+            debug!("Refusing UDP connection from {}, UDP connections are disabled", address);
+            return Err("UDP connections are currently disabled".into());
+        }
+
+        self.connections_by_kind[kind as usize].fetch_add(1, Ordering::Relaxed);
+
+        self.pending_logins.insert(connection_id, Instant::now());
+
+        let data = ClientData::new(kind);
+        data.set_data_request_rate(self.config.load().data_requests_per_second);
+        {
+            let config = self.config.load();
+            data.set_bandwidth_limits(
+                config.max_client_ingress_bandwidth,
+                config.max_client_egress_bandwidth,
+            );
+        }
+
+        Ok(data)
     }
 
     async fn on_client_disconnect(
@@ -216,6 +355,9 @@ impl AppHandler for ConnectionHandler {
     ) {
         debug!("Client disconnected: {} ({})", client.address, client.account_id());
 
+        self.connections_by_kind[client.connection_kind() as usize].fetch_sub(1, Ordering::Relaxed);
+        self.pending_logins.remove(&client.connection_id);
+
         if let Some(session) = client.take_session() {
             self.remove_from_session(client, &session);
         }
@@ -236,6 +378,37 @@ impl AppHandler for ConnectionHandler {
     ) {
         trace!(id = client.account_id(), cid = client.connection_id, "got {} bytes", data.len());
 
+        // reject huge messages before decoding, which could otherwise force large allocations
+        let (max_size, disconnect) = {
+            let config = self.config.load();
+            (config.max_message_bytes, config.disconnect_on_oversized_message)
+        };
+
+        if data.len() > max_size {
+            warn!(
+                "[{} @ {}] sent an oversized message ({} bytes, max {max_size})",
+                client.account_id(),
+                client.address,
+                data.len()
+            );
+
+            if disconnect {
+                client.disconnect("Message too large");
+            }
+
+            return;
+        }
+
+        if !self.check_bandwidth(client, TrafficDirection::Ingress, data.len()) {
+            return;
+        }
+
+        if !client.authorized() && self.auth_timed_out(client) {
+            warn!("[{}] did not log in in time, disconnecting", client.address);
+            client.disconnect("Login timed out");
+            return;
+        }
+
         let result = data::decode_message_match!(self, data, unpacked_data, {
             Login(msg) => {
                 let account_id = msg.get_account_id();
@@ -288,12 +461,18 @@ impl AppHandler for ConnectionHandler {
                 let mut data_requests = [0; 64];
                 let reqs = {
                     let in_reqs = msg.get_data_requests()?;
-                    for (i, val) in in_reqs.iter().take(64).enumerate() {
-                        data_requests[i] = val;
+                    let mut count = 0;
+
+                    // skip invalid and duplicate ids, so they don't cause needless lookups
+                    for val in in_reqs.iter().take(64) {
+                        if val != 0 && !data_requests[..count].contains(&val) {
+                            data_requests[count] = val;
+                            count += 1;
+                        }
                     }
-                    &data_requests[..(in_reqs.len().min(64u32) as usize)]
-                };
 
+                    &data_requests[..count]
+                };
 
                 let camera_range = CameraRange::new(msg.get_camera_x(), msg.get_camera_y(), msg.get_camera_radius());
                 let message_id = msg.get_message_id();
@@ -343,9 +522,12 @@ impl AppHandler for ConnectionHandler {
             },
 
             VoiceData(msg) => {
-                let msg = VoiceMessage::decode(client.account_id(), msg)?;
-
-                self.handle_voice_data(client, msg)
+                let max_size = self.config.load().max_voice_message_size;
+                // voice errors are reported as handler errors, so they are told apart from generic decode failures
+                match VoiceMessage::decode(client.account_id(), msg, max_size) {
+                    Ok(msg) => self.handle_voice_data(client, msg),
+                    Err(e) => Err(e.into()),
+                }
             },
 
             QuickChat(msg) => {
@@ -415,18 +597,45 @@ impl ConnectionHandler {
             token_issuer: ArcSwap::default(),
             roles: ArcSwap::default(),
             script_signer: ArcSwap::default(),
-            session_manager: Arc::new(SessionManager::new()),
+            session_manager: Arc::new(SessionManager::new(
+                config.max_sessions,
+                config.max_sessions_per_owner,
+                config.empty_session_grace_secs,
+                QueueLimits {
+                    max_events: config.max_queued_events,
+                    max_counters: config.max_queued_counters,
+                },
+                config
+                    .observer_address
+                    .clone()
+                    .map(|addr| Box::new(TcpObserverSink::spawn(addr)) as Box<dyn ObserverSink>),
+            )),
             clients: ClientStore::new(),
             all_rooms: DashMap::new(),
             user_cache: DashMap::new(),
             event_string_cache,
             legacy_event_encoder,
+            allowed_accounts: ArcSwap::new(Arc::new(build_allowlist(&config))),
+            banned_ips: ArcSwap::new(Arc::new(IpBanList::from_entries(&config.banned_ips))),
             config: ArcSwap::new(Arc::new(config)),
             total_connections: AtomicU64::new(0),
+            peak_clients: AtomicUsize::new(0),
+            interval_peak_clients: AtomicUsize::new(0),
             total_data_messages: AtomicU64::new(0),
+            message_counts: DashMap::new(),
+            pending_logins: DashMap::new(),
+            connections_by_kind: Default::default(),
             load_calculator,
             cached_status_data: Mutex::new(SrvStatusData::default()),
             cached_load: AtomicF32::new(0.0),
+            player_size_estimate: AtomicF32::new(0.0),
+            under_buffer_pressure: AtomicBool::new(false),
+            voice_drop_ratio: AtomicF32::new(0.0),
+            dropped_voice_messages: AtomicU64::new(0),
+            udp_connections_enabled: AtomicBool::new(true),
+            #[cfg(feature = "voice-processing")]
+            voice_processor: crate::voice_processor::create(),
+            started_at: Instant::now(),
         }
     }
 
@@ -443,6 +652,10 @@ impl ConnectionHandler {
         self.config.load().tickrate
     }
 
+    pub fn reject_old_timestamps(&self) -> bool {
+        self.config.load().reject_old_timestamps
+    }
+
     pub fn server_data(&self) -> &GameServerData {
         &self.data
     }
@@ -466,7 +679,7 @@ impl ConnectionHandler {
         let issuer = TokenIssuer::new(token_key, token_expiry)
             .map_err(|e| anyhow!("failed to create token issuer: {}", e))?;
         let signer = HmacSigner::new(script_key)
-            .map_err(|e| anyhow!("failed to create token issuer: {}", e))?;
+            .map_err(|e| anyhow!("failed to create script signer: {}", e))?;
 
         self.token_issuer.store(Arc::new(Some(issuer)));
         self.script_signer.store(Arc::new(Some(signer)));
@@ -478,6 +691,66 @@ impl ConnectionHandler {
 
     pub fn set_server_roles(&self, roles: Vec<ServerRole>) {
         self.roles.store(Arc::new(roles));
+        self.refresh_client_roles();
+    }
+
+    /// Recalculates permissions of all connected clients, for when role definitions change while they are connected
+    fn refresh_client_roles(&self) {
+        let server_roles = self.roles.load();
+
+        for client in self.clients.all() {
+            let current = client.special_data();
+
+            // clients that logged in while the bridge was down only have the raw role string
+            let pending = if server_roles.is_empty() {
+                None
+            } else {
+                client.take_pending_roles()
+            };
+
+            let roles = match (pending, &*current) {
+                (Some(roles_str), _) => {
+                    self.parse_roles(client.account_id(), client.address, &roles_str, &server_roles)
+                }
+                (None, Some(data)) => data.roles.clone(),
+                (None, None) => continue,
+            };
+
+            let name_color = current.as_ref().as_ref().and_then(|d| d.name_color.clone());
+
+            if roles.is_empty() && name_color.is_none() {
+                continue;
+            }
+
+            let (permissions, moderator) = resolve_role_permissions(&server_roles, &roles);
+
+            client.update_special_data(
+                client.account_id(),
+                Some(SpecialUserData { roles, name_color, permissions }),
+            );
+            client.set_moderator(moderator);
+        }
+    }
+
+    /// Maps a comma separated list of role string IDs to numeric role IDs, skipping unknown roles
+    fn parse_roles(
+        &self,
+        account_id: i32,
+        address: SocketAddr,
+        roles_str: &str,
+        server_roles: &[ServerRole],
+    ) -> heapless::Vec<u8, 64> {
+        let mut roles = heapless::Vec::new();
+
+        for role in roles_str.split(',').filter(|s| !s.is_empty()) {
+            if let Some(role) = server_roles.iter().find(|r| r.string_id == role) {
+                let _ = roles.push(role.id);
+            } else {
+                warn!("[{} @ {}] unknown role '{}' found in token", account_id, address, role);
+            }
+        }
+
+        roles
     }
 
     pub fn destroy_bridge_values(&self) {
@@ -513,6 +786,21 @@ impl ConnectionHandler {
 
         trace!("received user data ({data:?})");
 
+        // only a change of known data is a moderation action, the first data received about a user just describes them
+        let was_muted = self.user_cache.get(&data.account_id).map(|e| e.data.is_muted);
+
+        if let Some(was_muted) = was_muted
+            && was_muted != data.is_muted
+        {
+            let action = if data.is_muted { "mute" } else { "unmute" };
+            self.audit_log(
+                AuditActor::CentralServer,
+                action,
+                data.account_id,
+                "changed on the central server",
+            );
+        }
+
         let mut entry = self.user_cache.entry(data.account_id).or_insert_with(|| CachedUserData {
             data: SrvUserData::default(),
             accessed_at: now,
@@ -522,18 +810,28 @@ impl ConnectionHandler {
         entry.accessed_at = now;
     }
 
+    /// Removes clients that are gone but never got removed from the client store (e.g. after a failed disconnect)
+    pub fn vacuum_clients(&self) {
+        let removed = self.clients.vacuum();
+
+        if removed > 0 {
+            debug!("Removed {removed} dead entries from the client store");
+        }
+    }
+
     pub fn delete_from_user_data_cache(&self, account_id: i32) {
         self.user_cache.remove(&account_id);
     }
 
     pub fn cleanup_user_data_cache(&self) {
         let mut stale = HashSet::new();
+        let ttl = Duration::from_secs(self.config.load().user_cache_ttl_secs);
 
         for e in &mut self.user_cache.iter() {
             let id = e.key();
             let entry = e.value();
 
-            if entry.accessed_at.elapsed() > Duration::from_hours(3) {
+            if entry.accessed_at.elapsed() > ttl {
                 stale.insert(*id);
             }
         }
@@ -573,6 +871,18 @@ impl ConnectionHandler {
                 }
             };
 
+            if let Some(allowed) = self.allowed_accounts.load().as_ref()
+                && !allowed.contains(&token_data.account_id)
+            {
+                info!(
+                    "[{} @ {}] account is not allowlisted, rejecting login",
+                    token_data.account_id, client.address
+                );
+
+                client.disconnect("This server only allows specific accounts to join");
+                return Ok(false);
+            }
+
             self.on_login_success(client, token_data, icons, settings, event_encoder).await?;
 
             Ok(true)
@@ -596,8 +906,15 @@ impl ConnectionHandler {
         );
 
         self.total_connections.fetch_add(1, Ordering::Relaxed);
+        self.pending_logins.remove(&client.connection_id);
+
+        let old_client = self.clients.insert(token_data.account_id, client);
+
+        let client_count = self.clients.count();
+        self.peak_clients.fetch_max(client_count, Ordering::Relaxed);
+        self.interval_peak_clients.fetch_max(client_count, Ordering::Relaxed);
 
-        if let Some(old_client) = self.clients.insert(token_data.account_id, client) {
+        if let Some(old_client) = old_client {
             trace!("duplicate login detected for account ID {}", token_data.account_id);
 
             // there already was a client with this account ID, disconnect them
@@ -610,39 +927,35 @@ impl ConnectionHandler {
             ));
         }
 
-        // retrieve their roles
-        let roles = if let Some(roles_str) = token_data.roles_str.as_ref() {
-            let server_roles = self.roles.load();
-            let mut roles = heapless::Vec::new();
+        let server_roles = self.roles.load();
 
-            for role in roles_str.split(',').filter(|s| !s.is_empty()) {
-                if let Some(role) = server_roles.iter().find(|r| r.string_id == role) {
-                    let _ = roles.push(role.id);
-                } else {
-                    warn!(
-                        "[{} @ {}] unknown role '{}' found in token",
-                        token_data.account_id, client.address, role
-                    );
-                }
+        // retrieve their roles, if the bridge is down the role list is unknown, so keep the string for later
+        let roles = match token_data.roles_str.take() {
+            Some(roles_str) if !roles_str.is_empty() && server_roles.is_empty() => {
+                debug!(
+                    "[{} @ {}] server roles not known yet, deferring role resolution",
+                    token_data.account_id, client.address
+                );
+
+                client.set_pending_roles(roles_str);
+                heapless::Vec::new()
             }
 
-            roles
-        } else {
-            heapless::Vec::new()
+            Some(roles_str) => {
+                self.parse_roles(token_data.account_id, client.address, &roles_str, &server_roles)
+            }
+
+            None => heapless::Vec::new(),
         };
 
-        // free memory held by the role string and colors
+        // free memory held by the colors
         let name_color = token_data.name_color.take();
-        token_data.roles_str = None;
-
-        let server_roles = self.roles.load();
 
         // set roles and name color
         if !roles.is_empty() || name_color.is_some() {
-            let moderator =
-                roles.iter().any(|r| server_roles.iter().any(|sr| sr.id == *r && sr.can_moderate));
+            let (permissions, moderator) = resolve_role_permissions(&server_roles, &roles);
 
-            client.set_special_data(roles, name_color);
+            client.set_special_data(token_data.account_id, roles, name_color, permissions);
             client.set_moderator(moderator);
         }
 
@@ -690,6 +1003,24 @@ impl ConnectionHandler {
 
         let session_id = SessionId::from(session_id);
 
+        // rapid join/leave cycles create and delete sessions over and over, and flood scripts with join/leave events.
+        // there is no failure reason for this, and any existing one would mislead the client,
+        // so the join is delayed until the limiter allows it, the client keeps waiting for a response either way
+        if !client.try_session_change() {
+            debug!(
+                "[{} @ {}] joining sessions too quickly, delaying the join",
+                client.account_id(),
+                client.address
+            );
+
+            client.wait_session_change().await;
+
+            // joining after the client disconnected would leave it in the session forever
+            if !self.find_client(client.account_id()).is_some_and(|c| Arc::ptr_eq(&c, client)) {
+                return Ok(());
+            }
+        }
+
         if let Err(e) =
             self.do_join_session(client, session_id, passcode, platformer, editor_collab)
         {
@@ -712,14 +1043,23 @@ impl ConnectionHandler {
         platformer: bool,
         editor_collab: bool,
     ) -> Result<(), data::JoinSessionFailedReason> {
+        // the slot is still counted, so other players can't create sessions until it is freed up again
+        let bypass_limits = client.has_permission(RolePermissions::BYPASS_PLAYER_CAP);
+
         let new_session = if editor_collab {
-            self.session_manager.get_or_create_session(session.as_u64(), 0, platformer, true)
+            self.session_manager.get_or_create_session(
+                session.as_u64(),
+                0,
+                platformer,
+                true,
+                bypass_limits,
+            )
         } else {
             // ensure that the session is for a valid room
             let room_id = session.room_id();
             let owner;
 
-            if room_id != 0 {
+            if room_id != 0 || self.config.load().require_registered_rooms {
                 if let Some(room) = self.all_rooms.get(&room_id) {
                     if room.passcode != 0 && room.passcode != passcode {
                         debug!("incorrect passcode, expected {}, got {}", room.passcode, passcode);
@@ -735,7 +1075,21 @@ impl ConnectionHandler {
                 owner = 0;
             }
 
-            self.session_manager.get_or_create_session(session.as_u64(), owner, platformer, false)
+            self.session_manager.get_or_create_session(
+                session.as_u64(),
+                owner,
+                platformer,
+                false,
+                bypass_limits,
+            )
+        };
+
+        let new_session = match new_session {
+            Ok(s) => s,
+            Err(e) => {
+                debug!("failed to create session {}: {e}", session.as_u64());
+                return Err(data::JoinSessionFailedReason::ServerFull);
+            }
         };
 
         if let Some(old_session) = client.set_session(new_session.clone()) {
@@ -743,6 +1097,53 @@ impl ConnectionHandler {
         }
 
         new_session.add_player(client.account_id(), client.settings().hide_in_level);
+        self.session_manager.observe(ObserverEvent::PlayerJoin {
+            session: new_session.id,
+            account_id: client.account_id(),
+        });
+
+        if client.is_frozen() {
+            new_session.set_frozen(client.account_id(), true);
+        }
+
+        // let the player know if the owner picked a different tickrate for this session
+        let server_tickrate = self.tickrate();
+        let session_tickrate = new_session.tickrate(server_tickrate);
+
+        if session_tickrate != server_tickrate {
+            let event = self.to_owned_event(
+                &SessionTickrateEvent {
+                    tickrate: session_tickrate as u16,
+                },
+                Some(EventOptions {
+                    reliable: true,
+                    ..Default::default()
+                }),
+            );
+            new_session.push_event(client.account_id(), event);
+        }
+
+        if new_session.is_paused() {
+            let event = self.to_owned_event(
+                &SessionPauseEvent { paused: true },
+                Some(EventOptions {
+                    reliable: true,
+                    ..Default::default()
+                }),
+            );
+            new_session.push_event(client.account_id(), event);
+        }
+
+        if let Some(blob) = new_session.blob() {
+            let event = self.to_owned_event(
+                &blob.info_event(),
+                Some(EventOptions {
+                    reliable: true,
+                    ..Default::default()
+                }),
+            );
+            new_session.push_event(client.account_id(), event);
+        }
 
         #[cfg(feature = "scripting")]
         if let Some(sm) = new_session.scripting() {
@@ -767,6 +1168,30 @@ impl ConnectionHandler {
     fn remove_from_session(&self, client: &ClientStateHandle, session: &GameSession) {
         let account_id = client.account_id_force();
         session.remove_player(account_id);
+        self.session_manager.observe(ObserverEvent::PlayerLeave {
+            session: session.id,
+            account_id,
+        });
+
+        // don't leave the partner linked to a player that is gone
+        session.unlink_leaving_player(account_id, &self.event_string_cache);
+
+        if let Some(new_owner) = session.transfer_owner_from(account_id) {
+            debug!(
+                sid = session.id,
+                "owner {account_id} left, transferring ownership to {new_owner}"
+            );
+
+            let event = self.to_owned_event(
+                &SessionOwnerChangedEvent { owner: new_owner },
+                Some(EventOptions {
+                    reliable: true,
+                    ..Default::default()
+                }),
+            );
+            session.push_event_to_all(event);
+        }
+
         self.session_manager.delete_session_if_empty(session.id, session.editor_collab);
 
         #[cfg(feature = "scripting")]
@@ -782,15 +1207,28 @@ impl ConnectionHandler {
     ) -> HandlerResult<()> {
         client.set_icons(icons);
 
-        if let Some(session) = client.session() {
-            let event = self
-                .to_owned_event(&DisplayDataRefreshedEvent { player: client.account_id() }, None);
-            session.push_event_to_all(event);
+        if let Some(session) = client.session()
+            && client.try_icon_update()
+        {
+            self.broadcast_display_data_refresh(client, &session);
         }
 
         Ok(())
     }
 
+    /// Tells everyone else in the session to re-request the client's display data, which includes the icons
+    fn broadcast_display_data_refresh(&self, client: &ClientStateHandle, session: &GameSession) {
+        let event = self.to_owned_event(
+            &DisplayDataRefreshedEvent { player: client.account_id() },
+            Some(EventOptions {
+                reliable: true,
+                ..Default::default()
+            }),
+        );
+
+        session.push_event_to_all_except(event, client.account_id());
+    }
+
     async fn handle_player_data(
         &self,
         client: &ClientStateHandle,
@@ -806,6 +1244,28 @@ impl ConnectionHandler {
 
         // disallow account id spoofing
         let account_id = client.account_id();
+
+        // clients may leave the ID unset (0), only count an actual different ID as a violation
+        if data.account_id != 0 && data.account_id != account_id {
+            let violations = client.record_spoof_violation();
+            let max_violations = self.config.load().max_spoof_violations;
+
+            warn!(
+                "[{} @ {}] sent player data with a mismatched account ID ({}), violation {violations}",
+                account_id, client.address, data.account_id
+            );
+
+            if max_violations != 0 && violations >= max_violations {
+                warn!(
+                    "[{} @ {}] too many account ID mismatches, disconnecting client",
+                    account_id, client.address
+                );
+
+                client.disconnect("Sent data for a different account");
+                return Ok(());
+            }
+        }
+
         data.account_id = account_id;
 
         let Some(session) = client.session() else {
@@ -813,6 +1273,14 @@ impl ConnectionHandler {
             return Ok(());
         };
 
+        // send icon changes that were held back by the rate limit
+        if client.try_pending_icon_refresh() {
+            self.broadcast_display_data_refresh(client, &session);
+        }
+
+        // frozen players still get everyone else's data, but their events are ignored
+        let events = if client.is_frozen() { Vec::new() } else { events };
+
         for event in events {
             if let Err(e) = self.do_handle_event(client, &session, event) {
                 match e {
@@ -839,35 +1307,97 @@ impl ConnectionHandler {
         let mut out_events = SmallVec::<[OwnedEvent; 8]>::new();
         session.update_player(data, self, &mut out_events);
 
+        let is_mod = client.is_moderator();
+        let platformer = session.platformer;
+
+        let stale_timeout_ms = self.config.load().stale_player_timeout_ms;
+        let stale_timeout =
+            (stale_timeout_ms != 0).then(|| Duration::from_millis(stale_timeout_ms));
+
+        // all recipients in a session share one snapshot of everyone's state per tick
+        let tick_interval = Duration::from_secs(1) / session.tickrate(self.tickrate()) as u32;
+        let max_visible = self.config.load().max_visible_players;
+        let snapshot = session.build_tick_snapshot(tick_interval, stale_timeout, max_visible);
+
+        let scaled_range;
+        let camera_range = match snapshot.view_scale {
+            Some(factor) => {
+                scaled_range = camera_range.scaled(factor);
+                &scaled_range
+            }
+
+            None => camera_range,
+        };
+
+        // classic levels have no angle in the percentage field, clients can opt into getting bearings separately
+        if client.radar_enabled() && !platformer && out_events.len() < MAX_EVENT_COUNT {
+            let radar = build_radar(&snapshot, account_id, camera_range.center(), is_mod);
+
+            if !radar.entries.is_empty() {
+                out_events.push(self.to_owned_event(&radar, None));
+            }
+        }
+
         // remove events that the client does not understand
         out_events.retain(|e| client.event_encoder().knows_event(&e.id));
 
-        // TODO (high): adjust this
+        // reliable events are sent in their own message, so the player data around them can still be sent unreliably.
+        // no point in splitting if the client wants everything reliable anyway
+        let reliable_events: SmallVec<[OwnedEvent; 8]> = if client.reliable_player_data() {
+            SmallVec::new()
+        } else {
+            let (reliable, unreliable) = out_events.into_iter().partition(|e| e.options.reliable);
+            out_events = unreliable;
+            reliable
+        };
+
+        // used until there are enough samples for the size estimate
         const BYTES_PER_PLAYER: usize = 124; // this is an overshoot, for ext data
-        const BYTES_PER_REQUEST: usize = 70; // Rough estimate turned out to be ~67
+        const MAX_REQUEST_BYTES: usize = 4096;
+
+        // bound the amount of work spent on responding to metadata requests in a single packet,
+        // and ignore the ones that go over the client's budget
+        let mut resolved_requests: SmallVec<[(i32, Option<ClientStateHandle>); 64]> =
+            SmallVec::new();
+        let mut request_bytes = 0;
+
+        // when under pressure, clients will request them again later
+        if !self.under_buffer_pressure.load(Ordering::Relaxed) {
+            for &id in requests {
+                // stop before looking anything up once the client is out of requests
+                if !client.try_data_request() {
+                    break;
+                }
+
+                let target = self.find_client(id);
+                let size = target.as_ref().map_or(MISSING_DISPLAY_DATA_BYTES, display_data_size);
+
+                if request_bytes + size > MAX_REQUEST_BYTES {
+                    break;
+                }
+
+                request_bytes += size;
+                resolved_requests.push((id, target));
+            }
+        }
 
         let player_count = session.player_count();
 
-        let event_capacity = 16
-            + if client.event_encoder().is_legacy() {
-                out_events.iter().map(|x| x.data.len() + 2).sum::<usize>() // 2 for type
+        let events_capacity = |events: &[OwnedEvent]| {
+            16 + if client.event_encoder().is_legacy() {
+                events.iter().map(|x| x.data.len() + 2).sum::<usize>() // 2 for type
             } else {
-                out_events.iter().map(|x| x.max_encoded_size()).sum::<usize>()
-            };
-
-        let to_allocate = 96
-            + player_count * BYTES_PER_PLAYER
-            + requests.len() * BYTES_PER_REQUEST
-            + event_capacity;
+                events.iter().map(|x| x.max_encoded_size()).sum::<usize>()
+            }
+        };
 
-        // first encode events
-        let event_buf = if event_capacity > 0 {
-            let mut buf = self.server().request_buffer(event_capacity);
-            let window = unsafe { buf.write_window(event_capacity).unwrap() };
+        let encode_events = |events: &[OwnedEvent], capacity: usize| {
+            let mut buf = self.server().request_buffer(capacity);
+            let window = unsafe { buf.write_window(capacity).unwrap() };
             let mut writer = ByteWriter::new(window);
 
             // this should never fail provided there is enough space
-            match client.event_encoder().encode_events(&out_events, &mut writer) {
+            match client.event_encoder().encode_events(events, &mut writer) {
                 Ok(()) => {
                     let out_len = writer.written().len();
                     unsafe { buf.set_len(out_len) };
@@ -880,53 +1410,73 @@ impl ConnectionHandler {
                         "[{} @ {}] failed to encode {} events, dropping them: {e}",
                         client.account_id(),
                         client.address,
-                        out_events.len()
+                        events.len()
                     );
 
                     None
                 }
             }
+        };
+
+        let event_capacity = events_capacity(&out_events);
+
+        let per_player = size_estimate(&self.player_size_estimate, BYTES_PER_PLAYER);
+
+        let to_allocate = 96 + player_count * per_player + request_bytes + event_capacity;
+
+        // first encode events
+        let event_buf = if event_capacity > 0 {
+            encode_events(&out_events, event_capacity)
         } else {
             None
         };
 
-        let is_mod = client.is_moderator();
-        let platformer = session.platformer;
+        // in large sessions, only do exact distance checks for players the spatial index deems close enough
+        let mut nearby = client.nearby_players_buffer();
+        let has_index =
+            snapshot.players_near(camera_range.center(), camera_range.radius(), &mut nearby);
 
-        let mut color_buf = [0u8; 256];
+        let event_len = event_buf.as_ref().map_or(0, |b| b.len());
+
+        let mut color_buf = [0u8; MAX_NAME_COLOR_SIZE];
 
         let buf = data::encode_message_heap!(self, to_allocate, msg => {
             let mut level_data = msg.reborrow().init_level_data();
             let mut players_data = level_data.reborrow().init_players(player_count as u32);
             let mut written_players = 0;
 
-            session.for_every_player(|player| {
+            for player in &snapshot.players {
                 if written_players == player_count {
-                    return;
+                    break;
                 }
 
                 // comment this for testing xd
                 if player.state.account_id == account_id {
-                    return;
+                    continue;
                 }
 
                 if player.wants_hidden && !is_mod {
-                    return;
+                    continue;
                 }
 
+                // don't show players that stopped sending updates at their last position
+                let culled =
+                    player.stale || (has_index && !nearby.contains(&player.state.account_id));
+
                 let mut p = players_data.reborrow().get(written_players as u32);
-                player.state.encode(p.reborrow(), platformer, camera_range);
+                player.state.encode(p.reborrow(), platformer, camera_range, culled);
 
                 written_players += 1;
-            });
+            }
 
             // encode responses to player metadata requests
 
-            let mut reqs_data = level_data.reborrow().init_display_datas(requests.len() as u32);
-            for (i, req) in requests.iter().enumerate() {
+            let mut reqs_data =
+                level_data.reborrow().init_display_datas(resolved_requests.len() as u32);
+            for (i, (req, target)) in resolved_requests.iter().enumerate() {
                 let mut p = reqs_data.reborrow().get(i as u32);
 
-                if let Some(client) = self.find_client(*req) && let Some(adata) = client.account_data() {
+                if let Some(client) = target && let Some(adata) = client.account_data() {
                     let settings = client.settings();
                     // don't send if they wanna be hidden and we aren't a moderator
                     if is_mod || !settings.hide_in_level {
@@ -936,7 +1486,7 @@ impl ConnectionHandler {
                         p.set_username(adata.username.as_str());
                         icons.encode(p.reborrow().init_icons());
 
-                        if let Some(sud) = client.special_data() && (is_mod || !settings.hide_roles) {
+                        if let Some(sud) = &*client.special_data() && (is_mod || !settings.hide_roles) {
                             let mut p = p.init_special_data();
 
                             if let Err(e) = p.reborrow().set_roles(sud.roles.as_slice()) {
@@ -971,11 +1521,55 @@ impl ConnectionHandler {
             level_data.set_message_id(message_id);
         })?;
 
-        // events might make the message reliable
-        if out_events.iter().any(|e| e.options.reliable) {
+        self.record_level_data_size(buf.len(), event_len, player_count, resolved_requests.len());
+
+        // the next tick supersedes this data, so it can be dropped even when it would be sent reliably
+        let within_budget = self.check_bandwidth(client, TrafficDirection::Egress, buf.len());
+
+        if within_budget && client.reliable_player_data() {
             client.send_data_bufkind(buf);
-        } else {
+        } else if within_budget {
+            #[cfg(not(feature = "debug-netsim"))]
             client.send_unreliable_data_bufkind(buf);
+
+            #[cfg(feature = "debug-netsim")]
+            {
+                let (loss, latency_ms) = {
+                    let config = self.config.load();
+                    (config.debug_packet_loss, config.debug_added_latency_ms)
+                };
+
+                if client.netsim_should_drop(loss) {
+                    trace!("[{}] netsim: dropping player data packet", client.address);
+                } else if latency_ms == 0 {
+                    client.send_unreliable_data_bufkind(buf);
+                } else {
+                    let client = client.clone();
+
+                    crate::tokio::spawn(async move {
+                        crate::tokio::time::sleep(Duration::from_millis(latency_ms)).await;
+                        client.send_unreliable_data_bufkind(buf);
+                    });
+                }
+            }
+        }
+
+        // reliable events go out even if the player data was dropped for bandwidth, with no player data attached
+        if !reliable_events.is_empty()
+            && let Some(event_buf) =
+                encode_events(&reliable_events, events_capacity(&reliable_events))
+        {
+            let buf = data::encode_message_heap!(self, 96 + event_buf.len(), msg => {
+                let mut level_data = msg.reborrow().init_level_data();
+                level_data.reborrow().init_players(0);
+                level_data.reborrow().init_display_datas(0);
+                level_data.reborrow().set_event_data(&event_buf);
+                level_data.set_message_id(message_id);
+            })?;
+
+            // events are not superseded by later ticks, so they are counted but never dropped
+            client.record_bandwidth(TrafficDirection::Egress, buf.len());
+            client.send_data_bufkind(buf);
         }
 
         Ok(())
@@ -1016,7 +1610,7 @@ impl ConnectionHandler {
     fn do_handle_event(
         &self,
         client: &ClientStateHandle,
-        session: &GameSession,
+        session: &Arc<GameSession>,
         event: OwnedEvent,
     ) -> HandlerResult<()> {
         must_auth(client)?;
@@ -1032,7 +1626,7 @@ impl ConnectionHandler {
 
             #[cfg(feature = "scripting")]
             "globed/scripting.request-script-logs" => {
-                if session.owner != client.account_id() {
+                if session.owner() != client.account_id() {
                     return Ok(());
                 }
 
@@ -1058,46 +1652,235 @@ impl ConnectionHandler {
                 client.send_data_bufkind(buf);
             }
 
-            _ => {
-                // generic event code, forward to everybody who needs to see it
-
-                let out_event = OwnedEvent {
-                    id: event.id,
-                    data: event.data,
-                    options: EventOptions {
-                        target_players: Vec::new(),
-                        sent_by_player: client.account_id_nz(),
-                        ..event.options
-                    },
-                };
-
-                // calculate how many targets in total there are, to check the rate limits
-                let targets = if event.options.target_players.is_empty() {
-                    session.player_count()
-                } else {
-                    event.options.target_players.len()
-                };
-
-                if !client.try_event(targets, out_event.data.len(), out_event.options.reliable) {
-                    return Err(HandlerError::EventRateLimit);
+            #[cfg(feature = "scripting")]
+            "globed/scripting.stream-logs" => {
+                if session.owner() != client.account_id() {
+                    return Ok(());
                 }
 
-                if event.options.target_players.is_empty() {
-                    if event.options.send_back {
-                        session.push_event_to_all(out_event);
-                    } else {
-                        session.push_event_to_all_except(out_event, client.account_id());
-                    }
-                } else {
-                    for target in &event.options.target_players {
-                        session.push_event(*target, out_event.clone());
-                    }
-                }
+                let event = ScriptLogStreamEvent::decode(&event.data)?;
+                session.set_stream_logs(event.enabled);
             }
-        }
 
-        Ok(())
-    }
+            "globed/player-data.options" => {
+                let event = PlayerDataOptionsEvent::decode(&event.data)?;
+                client.set_reliable_player_data(event.reliable);
+            }
+
+            "globed/player.set-hidden" => {
+                let event = SetHiddenEvent::decode(&event.data)?;
+                session.set_wants_hidden(client.account_id(), event.hidden);
+            }
+
+            "globed/mod.freeze-user" => {
+                let event = FreezeUserEvent::decode(&event.data)?;
+
+                if !client.is_moderator() && !client.has_permission(RolePermissions::MODERATE) {
+                    warn!(
+                        "[{} @ {}] tried to freeze a user without permission",
+                        client.account_id(),
+                        client.address
+                    );
+
+                    return Ok(());
+                }
+
+                self.set_user_frozen(
+                    AuditActor::Player(client.account_id()),
+                    event.account_id,
+                    event.frozen,
+                );
+            }
+
+            "globed/mod.dump-session" => {
+                if !client.is_moderator() && !client.has_permission(RolePermissions::MODERATE) {
+                    return Ok(());
+                }
+
+                info!(
+                    "[{} @ {}] requested a session dump:\n{}",
+                    client.account_id(),
+                    client.address,
+                    session.debug_dump()
+                );
+            }
+
+            "globed/session.tickrate" => {
+                let event = SessionTickrateEvent::decode(&event.data)?;
+                self.handle_set_session_tickrate(client, session, event.tickrate as usize);
+            }
+
+            "globed/session.pause" => {
+                let event = SessionPauseEvent::decode(&event.data)?;
+                self.handle_set_session_paused(client, session, event.paused);
+            }
+
+            "globed/voice.set-channel" => {
+                let event = SetVoiceChannelEvent::decode(&event.data)?;
+                session.set_voice_channel(client.account_id(), event.channel);
+            }
+
+            "globed/radar.options" => {
+                let event = RadarOptionsEvent::decode(&event.data)?;
+                client.set_radar_enabled(event.enabled);
+            }
+
+            "globed/chat.message" => {
+                self.handle_chat_message(client, session, &event.data)?;
+            }
+
+            "globed/session.set-blob" => {
+                self.handle_set_session_blob(client, session, &event.data)?;
+            }
+
+            "globed/session.request-blob" => {
+                if let Some(blob) = session.blob() {
+                    if !client.try_event(1, blob.data.len(), true) {
+                        return Err(HandlerError::EventRateLimit);
+                    }
+
+                    let event = self.to_owned_event(
+                        &SessionBlobEvent {
+                            version: blob.version,
+                            data: &blob.data,
+                        },
+                        Some(EventOptions {
+                            reliable: true,
+                            ..Default::default()
+                        }),
+                    );
+                    session.push_event(client.account_id(), event);
+                }
+            }
+
+            "globed/scripting.custom" => {
+                let custom = CustomScriptEvent::decode(&event.data)?;
+
+                let allowed = {
+                    let config = self.config.load();
+                    config.allowed_scripted_events.is_empty()
+                        || config.allowed_scripted_events.contains(&custom.event_type)
+                };
+
+                if !allowed {
+                    debug!(
+                        "[{} @ {}] dropping scripted event with type {} that is not allowed",
+                        client.account_id(),
+                        client.address,
+                        custom.event_type
+                    );
+
+                    return Ok(());
+                }
+
+                self.forward_event(client, session, event)?;
+            }
+
+            "globed/scripting.spawn-group" => {
+                // reject malformed remaps before they reach other clients
+                let _ = SpawnGroupEvent::decode(&event.data)?;
+                self.forward_event(client, session, event)?;
+            }
+
+            "globed/2p.link" => {
+                // the target accepts by sending a link request back
+                if let &[target] = event.options.target_players.as_slice()
+                    && target != client.account_id()
+                    && session.has_player(target)
+                    && session.request_link(client.account_id(), target)
+                        == LinkRequestOutcome::Refused
+                {
+                    debug!(
+                        "[{} @ {}] tried to link with player {} while one of them is already linked",
+                        client.account_id(),
+                        client.address,
+                        target
+                    );
+
+                    return Ok(());
+                }
+
+                self.forward_event(client, session, event)?;
+            }
+
+            "globed/2p.unlink" => {
+                session.unlink_player(client.account_id());
+                self.forward_event(client, session, event)?;
+            }
+
+            "globed/scripting.follow-player" => {
+                let follow = FollowPlayerEvent::decode(&event.data)?;
+
+                // following someone who isn't here would leave the group stuck on clients.
+                // disabling is always forwarded, so groups can be released after the target left
+                if follow.enable && !session.has_player(follow.player_id) {
+                    debug!(
+                        "[{} @ {}] tried to follow player {} who is not in the session",
+                        client.account_id(),
+                        client.address,
+                        follow.player_id
+                    );
+
+                    return Ok(());
+                }
+
+                self.forward_event(client, session, event)?;
+            }
+
+            "globed/scripting.follow-rotation" => {
+                // validate before forwarding
+                let _ = FollowRotationEvent::decode(&event.data)?;
+                self.forward_event(client, session, event)?;
+            }
+
+            _ => self.forward_event(client, session, event)?,
+        }
+
+        Ok(())
+    }
+
+    /// Generic event code, forwards the event to everybody who needs to see it
+    fn forward_event(
+        &self,
+        client: &ClientStateHandle,
+        session: &GameSession,
+        event: OwnedEvent,
+    ) -> HandlerResult<()> {
+        let out_event = OwnedEvent {
+            id: event.id,
+            data: event.data,
+            options: EventOptions {
+                target_players: Vec::new(),
+                sent_by_player: client.account_id_nz(),
+                ..event.options
+            },
+        };
+
+        // calculate how many targets in total there are, to check the rate limits
+        let targets = if event.options.target_players.is_empty() {
+            session.player_count()
+        } else {
+            event.options.target_players.len()
+        };
+
+        if !client.try_event(targets, out_event.data.len(), out_event.options.reliable) {
+            return Err(HandlerError::EventRateLimit);
+        }
+
+        if event.options.target_players.is_empty() {
+            if event.options.send_back {
+                session.push_event_to_all(out_event);
+            } else {
+                session.push_event_to_all_except(out_event, client.account_id());
+            }
+        } else {
+            for target in &event.options.target_players {
+                session.push_event(*target, out_event.clone());
+            }
+        }
+
+        Ok(())
+    }
 
     fn handle_send_level_script(
         &self,
@@ -1116,12 +1899,12 @@ impl ConnectionHandler {
             return Ok(());
         };
 
-        if client.account_id() != session.owner {
+        if client.account_id() != session.owner() {
             debug!(
                 "[{} @ {}] got SendLevelScript from non-room owner (owner is {})",
                 client.account_id(),
                 client.address,
-                session.owner
+                session.owner()
             );
 
             return Ok(());
@@ -1140,6 +1923,19 @@ impl ConnectionHandler {
 
         #[cfg(feature = "scripting")]
         {
+            let (max_count, max_bytes) = {
+                let config = self.config.load();
+                (config.max_scripts_per_session, config.max_script_upload_bytes)
+            };
+
+            if let Err(e) = validate_scripts(scripts, max_count, max_bytes) {
+                session.log_script_message(&format!("[ERROR] scripts rejected: {e}"));
+
+                warn!("[{} @ {}] scripts rejected: {e}", client.account_id(), client.address);
+
+                return Ok(());
+            }
+
             // verify script signatures
             if self.config.load().verify_script_signatures {
                 let Some(signer) = &**self.script_signer.load() else {
@@ -1188,19 +1984,30 @@ impl ConnectionHandler {
         must_auth(client)?;
 
         let Some(session) = client.session() else {
-            warn!(
-                "[{} @ {}] got VoiceDataMessage while not in session",
-                client.account_id(),
-                client.address
-            );
+            return Err(VoiceError::NotInSession.into());
+        };
 
+        if !self.check_can_talk(client, ChatKind::Voice)? {
             return Ok(());
-        };
+        }
 
-        if !self.check_can_talk(client, true)? {
+        // shed load before spending any time on processing and encoding
+        let drop_ratio = self.voice_drop_ratio.load(Ordering::Relaxed);
+        if drop_ratio > 0.0 && rand::random::<f32>() < drop_ratio {
+            self.dropped_voice_messages.fetch_add(1, Ordering::Relaxed);
             return Ok(());
         }
 
+        // the message was just decoded, so this is the only reference
+        #[cfg(feature = "voice-processing")]
+        let vmsg = {
+            let mut vmsg = vmsg;
+            if let Some(msg) = Arc::get_mut(&mut vmsg) {
+                self.voice_processor.process(msg);
+            }
+            vmsg
+        };
+
         // broadcast message to everyone
 
         let buf = Arc::new(data::encode_message_heap!(self, vmsg.encoded_len(), msg => {
@@ -1209,23 +2016,82 @@ impl ConnectionHandler {
 
         debug!("broadcasting voice message from {} ({} bytes)", client.account_id(), buf.len());
 
-        session.for_every_player_id(|id| {
-            if id != client.account_id() {
-                if let Some(c) = self.find_client(id) {
-                    c.send_data_bufkind_opts(
-                        BufferKind::Reference(buf.clone()),
-                        QunetMessageOpts {
-                            reliable: false,
-                            uncompressed: true,
-                        },
-                    );
-                }
+        for id in session.voice_recipients(client.account_id()) {
+            if let Some(c) = self.find_client(id)
+                && self.check_bandwidth(&c, TrafficDirection::Egress, buf.len())
+            {
+                c.send_data_bufkind_opts(
+                    BufferKind::Reference(buf.clone()),
+                    QunetMessageOpts {
+                        reliable: false,
+                        uncompressed: true,
+                    },
+                );
             }
-        });
+        }
 
         Ok(())
     }
 
+    fn auth_timed_out(&self, client: &ClientStateHandle) -> bool {
+        let timeout = self.config.load().auth_timeout_secs;
+        timeout != 0 && client.connected_at().elapsed() > Duration::from_secs(timeout)
+    }
+
+    /// Disconnects clients that connected more than `auth_timeout_secs` ago and still haven't logged in,
+    /// including ones that never sent anything. `on_client_data` checks the same thing as a fast path.
+    fn sweep_login_timeouts(&self) {
+        let timeout = Duration::from_secs(self.config.load().auth_timeout_secs);
+
+        if timeout.is_zero() {
+            return;
+        }
+
+        let expired = self
+            .pending_logins
+            .iter()
+            .filter(|e| e.value().elapsed() > timeout)
+            .map(|e| *e.key())
+            .collect::<Vec<_>>();
+
+        let server = self.server();
+
+        for connection_id in expired {
+            self.pending_logins.remove(&connection_id);
+
+            if let Some(client) = server.get_client(connection_id)
+                && !client.authorized()
+            {
+                warn!("[{}] did not log in in time, disconnecting", client.address);
+                client.disconnect("Login timed out");
+            }
+        }
+    }
+
+    /// Records traffic to or from the client, returns `false` if it is over its bandwidth budget and the data should be dropped
+    fn check_bandwidth(
+        &self,
+        client: &ClientStateHandle,
+        direction: TrafficDirection,
+        bytes: usize,
+    ) -> bool {
+        match client.record_bandwidth(direction, bytes) {
+            BandwidthState::Ok => true,
+
+            BandwidthState::Throttled => {
+                warn!(
+                    "[{} @ {}] exceeded the {} bandwidth limit, throttling",
+                    client.account_id(),
+                    client.address,
+                    direction.as_str()
+                );
+                false
+            }
+
+            BandwidthState::StillThrottled => false,
+        }
+    }
+
     fn handle_quick_chat(&self, client: &ClientStateHandle, id: u32) -> HandlerResult<()> {
         must_auth(client)?;
 
@@ -1239,7 +2105,7 @@ impl ConnectionHandler {
             return Ok(());
         };
 
-        if !self.check_can_talk(client, false)? {
+        if !self.check_can_talk(client, ChatKind::Quick)? {
             return Ok(());
         }
 
@@ -1264,40 +2130,181 @@ impl ConnectionHandler {
         Ok(())
     }
 
-    fn check_can_talk(&self, client: &ClientStateHandle, is_voice: bool) -> HandlerResult<bool> {
-        let outcome = self
-            .get_cached_user(client.account_id())
-            .map(|u| {
-                // 1. check rate limit
-                let able = if is_voice {
-                    client.data().try_voice_chat()
-                } else {
-                    client.data().try_quick_chat()
-                };
+    fn handle_set_session_tickrate(
+        &self,
+        client: &ClientStateHandle,
+        session: &GameSession,
+        tickrate: usize,
+    ) {
+        if session.owner() != client.account_id() {
+            return;
+        }
 
-                if !able {
-                    return CanTalkOutcome::RateLimited;
-                }
+        let server_tickrate = self.tickrate();
 
-                let allowed = if is_voice {
-                    u.data.can_use_voice
-                } else {
-                    u.data.can_use_quick_chat
-                };
+        if !(MIN_SESSION_TICKRATE..=server_tickrate).contains(&tickrate) {
+            debug!(
+                "[{} @ {}] rejecting session tickrate {tickrate}, allowed range is {MIN_SESSION_TICKRATE}..={server_tickrate}",
+                client.account_id(),
+                client.address
+            );
 
-                if allowed {
-                    CanTalkOutcome::Allowed
-                } else if u.data.is_muted {
-                    CanTalkOutcome::Muted
-                } else if !u.data.is_linked {
-                    CanTalkOutcome::NotLinked
-                } else {
-                    // should never happen?
-                    CanTalkOutcome::Disallowed
-                }
-            })
-            // return unknown if we don't have any data yet
-            .unwrap_or(CanTalkOutcome::Unknown);
+            return;
+        }
+
+        debug!(sid = session.id, "owner changed session tickrate to {tickrate}");
+        session.set_tickrate(tickrate);
+
+        let event = self.to_owned_event(
+            &SessionTickrateEvent { tickrate: tickrate as u16 },
+            Some(EventOptions {
+                reliable: true,
+                ..Default::default()
+            }),
+        );
+        session.push_event_to_all(event);
+    }
+
+    fn handle_set_session_paused(
+        &self,
+        client: &ClientStateHandle,
+        session: &Arc<GameSession>,
+        paused: bool,
+    ) {
+        if session.owner() != client.account_id() {
+            return;
+        }
+
+        if session.set_paused(paused) == paused {
+            return;
+        }
+
+        debug!(sid = session.id, "owner {} the session", if paused { "paused" } else { "resumed" });
+
+        let event = self.to_owned_event(
+            &SessionPauseEvent { paused },
+            Some(EventOptions {
+                reliable: true,
+                ..Default::default()
+            }),
+        );
+        session.push_event_to_all(event);
+
+        // heartbeats are not scheduled while paused, so start them again
+        #[cfg(feature = "scripting")]
+        if !paused && session.scripting().is_some() {
+            session.schedule_heartbeat();
+        }
+    }
+
+    fn handle_set_session_blob(
+        &self,
+        client: &ClientStateHandle,
+        session: &GameSession,
+        data: &[u8],
+    ) -> HandlerResult<()> {
+        if session.owner() != client.account_id() {
+            return Ok(());
+        }
+
+        if data.len() > MAX_SESSION_BLOB_SIZE {
+            debug!(
+                "[{} @ {}] rejecting session data of {} bytes, limit is {MAX_SESSION_BLOB_SIZE}",
+                client.account_id(),
+                client.address,
+                data.len()
+            );
+
+            return Ok(());
+        }
+
+        // every player will likely request the data, so count it against the owner's event budget
+        if !client.try_event(session.player_count(), data.len(), true) {
+            return Err(HandlerError::EventRateLimit);
+        }
+
+        let blob = session.set_blob(data.to_vec());
+        debug!(
+            sid = session.id,
+            "owner set session data (version {}, {} bytes)",
+            blob.version,
+            data.len()
+        );
+
+        let event = self.to_owned_event(
+            &blob.info_event(),
+            Some(EventOptions {
+                reliable: true,
+                ..Default::default()
+            }),
+        );
+        session.push_event_to_all_except(event, client.account_id());
+
+        Ok(())
+    }
+
+    fn handle_chat_message(
+        &self,
+        client: &ClientStateHandle,
+        session: &GameSession,
+        data: &[u8],
+    ) -> HandlerResult<()> {
+        let event = ChatMessageEvent::decode(client.account_id(), data)?;
+
+        if !self.check_can_talk(client, ChatKind::Text)? {
+            return Ok(());
+        }
+
+        debug!("relaying chat message from {} ({} bytes)", client.account_id(), event.text.len());
+
+        let event = self.to_owned_event(
+            &event,
+            Some(EventOptions {
+                reliable: true,
+                ..Default::default()
+            }),
+        );
+        session.push_event_to_all_except(event, client.account_id());
+
+        Ok(())
+    }
+
+    fn check_can_talk(&self, client: &ClientStateHandle, kind: ChatKind) -> HandlerResult<bool> {
+        // 1. check rate limit
+        let able = match kind {
+            ChatKind::Voice => client.data().try_voice_chat(),
+            ChatKind::Quick => client.data().try_quick_chat(),
+            ChatKind::Text => client.data().try_text_chat(),
+        };
+
+        let outcome = if !able {
+            CanTalkOutcome::RateLimited
+        } else if kind == ChatKind::Voice && client.has_permission(RolePermissions::VOICE_ALWAYS) {
+            // 2. roles that can always talk don't need to wait for the central server
+            CanTalkOutcome::Allowed
+        } else {
+            self.get_cached_user(client.account_id())
+                .map(|u| {
+                    let allowed = match kind {
+                        ChatKind::Voice => u.data.can_use_voice,
+                        ChatKind::Quick => u.data.can_use_quick_chat,
+                        ChatKind::Text => !u.data.is_muted,
+                    };
+
+                    if allowed {
+                        CanTalkOutcome::Allowed
+                    } else if u.data.is_muted {
+                        CanTalkOutcome::Muted
+                    } else if !u.data.is_linked {
+                        CanTalkOutcome::NotLinked
+                    } else {
+                        // should never happen?
+                        CanTalkOutcome::Disallowed
+                    }
+                })
+                // return unknown if we don't have any data yet
+                .unwrap_or(CanTalkOutcome::Unknown)
+        };
 
         if outcome != CanTalkOutcome::Allowed {
             debug!(
@@ -1308,7 +2315,7 @@ impl ConnectionHandler {
 
             let buf = data::encode_message!(self, 48, msg => {
                 let mut nperm = msg.reborrow().init_chat_not_permitted();
-                nperm.set_is_voice(is_voice);
+                nperm.set_is_voice(kind == ChatKind::Voice);
                 nperm.set_reason(match outcome {
                     CanTalkOutcome::NotLinked => data::ChatNotPermittedReason::NotLinked,
                     CanTalkOutcome::Muted => data::ChatNotPermittedReason::Muted,
@@ -1343,7 +2350,7 @@ impl ConnectionHandler {
         );
         info!("Total keepalives exchanged: {}", overall.total_keepalives);
 
-        let base_dir = std::env::current_dir().unwrap().join("conn-dumps");
+        let base_dir = std::env::current_dir().unwrap().join(&self.config.load().stat_dump_dir);
 
         info!("Dumping {} connections to {base_dir:?}", conns.len());
 
@@ -1366,6 +2373,353 @@ impl ConnectionHandler {
         Some(overall)
     }
 
+    /// Checks heap usage of the buffer pool, and throttles non-essential work if it's too close to the limit.
+    fn check_buffer_pressure(&self) {
+        let (usage, percent, voice_percent, voice_max_ratio) = {
+            let config = self.config.load();
+            (
+                config.memory_usage,
+                config.buffer_pressure_percent,
+                config.voice_shedding_percent,
+                config.voice_shedding_max_ratio,
+            )
+        };
+
+        let (_, max_mem, _, _) = server_shared::config::make_memory_limits(usage);
+        let heap_usage = self.server().get_buffer_pool().stats().total_heap_usage as u64;
+
+        self.update_voice_drop_ratio(heap_usage, max_mem as u64, voice_percent, voice_max_ratio);
+
+        if percent == 0 {
+            self.under_buffer_pressure.store(false, Ordering::Relaxed);
+            return;
+        }
+
+        let threshold = max_mem as u64 * percent / 100;
+
+        let pressure = heap_usage > threshold;
+        let was_pressure = self.under_buffer_pressure.swap(pressure, Ordering::Relaxed);
+
+        if pressure && !was_pressure {
+            warn!(
+                "Buffer pool is under pressure ({heap_usage} / {threshold} bytes), throttling metadata requests"
+            );
+        } else if !pressure && was_pressure {
+            info!("Buffer pool pressure subsided ({heap_usage} bytes), no longer throttling");
+        }
+    }
+
+    /// Drops voice messages with a probability that grows linearly from 0 at `start_percent` of the buffer pool limit
+    /// to `max_ratio` when the pool is full, since voice is the least important data to lose.
+    fn update_voice_drop_ratio(
+        &self,
+        heap_usage: u64,
+        max_mem: u64,
+        start_percent: u64,
+        max_ratio: f32,
+    ) {
+        let start = max_mem * start_percent / 100;
+
+        let ratio = if start_percent == 0 || max_mem == 0 || heap_usage <= start {
+            0.0
+        } else {
+            let over = (heap_usage - start) as f32 / (max_mem - start).max(1) as f32;
+            over.min(1.0) * max_ratio
+        };
+
+        let prev = self.voice_drop_ratio.swap(ratio, Ordering::Relaxed);
+
+        if ratio > 0.0 && prev == 0.0 {
+            warn!(
+                "Buffer pool usage is high ({heap_usage} bytes), dropping {:.0}% of voice messages",
+                ratio * 100.0
+            );
+        } else if ratio == 0.0 && prev > 0.0 {
+            info!(
+                "Buffer pool usage went down ({heap_usage} bytes), no longer dropping voice messages"
+            );
+        }
+    }
+
+    /// Logs application-level server status, complementing the transport stats printed by qunet.
+    pub fn print_status(&self) {
+        let kind_count =
+            |k: ConnectionKind| self.connections_by_kind[k as usize].load(Ordering::Relaxed);
+
+        info!("== Game server status ==");
+        info!("Version: {}, build date: {}", Self::version(), Self::build_date());
+        info!("Uptime: {}s", self.uptime().as_secs());
+
+        let bridge_state = if self.bridge.is_connected() && self.bridge.is_authenticated() {
+            "connected"
+        } else if self.bridge.is_connected() {
+            "connected, not logged in"
+        } else if self.bridge.is_connecting() {
+            "connecting"
+        } else {
+            "disconnected"
+        };
+
+        info!("Central server: {bridge_state} ({})", self.bridge.server_url());
+        info!(
+            "Connections: {} TCP, {} UDP, {} QUIC, {} other",
+            kind_count(ConnectionKind::Tcp),
+            kind_count(ConnectionKind::Udp),
+            kind_count(ConnectionKind::Quic),
+            kind_count(ConnectionKind::Other)
+        );
+
+        let stats = self.session_manager.stats();
+        let lifetimes = stats
+            .lifetimes
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                let count = count.load(Ordering::Relaxed);
+
+                match SESSION_LIFETIME_BUCKETS.get(i) {
+                    Some(bound) => format!("<{bound:?}: {count}"),
+                    None => format!(">={:?}: {count}", SESSION_LIFETIME_BUCKETS[i - 1]),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        info!(
+            "Sessions: {} active, {} created in total",
+            self.session_manager.count(),
+            stats.total_created.load(Ordering::Relaxed)
+        );
+        info!(
+            "Players: {} authorized clients, {} in sessions",
+            self.clients.count(),
+            self.session_manager.all_sessions().iter().map(|s| s.player_count()).sum::<usize>()
+        );
+        info!(
+            "Peak players: {} since the last report, {} since launch",
+            // the next interval starts from the current count, not 0
+            self.interval_peak_clients.swap(self.clients.count(), Ordering::Relaxed),
+            self.peak_clients.load(Ordering::Relaxed)
+        );
+        info!("Session lifetimes: {lifetimes}");
+        info!(
+            "Caches: {} users, {} rooms, {} bytes in the buffer pool",
+            self.user_cache.len(),
+            self.all_rooms.len(),
+            self.server().get_buffer_pool().stats().total_heap_usage
+        );
+        info!(
+            "Level data size estimate: {:.1} bytes per player",
+            self.player_size_estimate.load(Ordering::Relaxed)
+        );
+        info!(
+            "Events dropped due to full queues: {}",
+            stats.dropped_events.load(Ordering::Relaxed)
+        );
+        info!(
+            "Voice messages dropped due to load: {} (currently dropping {:.0}%)",
+            self.dropped_voice_messages.load(Ordering::Relaxed),
+            self.voice_drop_ratio.load(Ordering::Relaxed) * 100.0
+        );
+
+        let mut message_counts = self
+            .message_counts
+            .iter()
+            .map(|e| (*e.key(), e.value().load(Ordering::Relaxed)))
+            .collect::<Vec<_>>();
+        message_counts.sort_unstable_by(|a, b| b.1.cmp(&a.1));
+
+        let message_counts = message_counts
+            .iter()
+            .map(|(name, count)| format!("{name}: {count}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        info!("Messages received: {message_counts}");
+    }
+
+    /// Counts a received message of the given type, called from `decode_message_match!`
+    pub fn record_message_kind(&self, kind: &'static str) {
+        if let Some(count) = self.message_counts.get(kind) {
+            count.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.message_counts.entry(kind).or_default().fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn start_health_check(&self, server: &QunetServerHandle<Self>) {
+        let Some(address) = &self.config.load().health_check_address else {
+            return;
+        };
+
+        let address = match address.parse() {
+            Ok(x) => x,
+            Err(e) => {
+                error!("invalid health check address '{address}': {e}");
+                return;
+            }
+        };
+
+        crate::tokio::spawn(crate::health::run(address, server.make_weak()));
+    }
+
+    pub fn health_state(&self) -> HealthState {
+        if self.bridge.is_connected() && self.bridge.is_authenticated() {
+            HealthState::Ready
+        } else if self.bridge.has_authenticated() {
+            HealthState::Degraded
+        } else {
+            HealthState::Starting
+        }
+    }
+
+    #[cfg(feature = "admin-api")]
+    fn start_admin_api(&self, server: &QunetServerHandle<Self>) {
+        let config = self.config.load();
+
+        let (Some(address), Some(token)) = (&config.admin_api_address, &config.admin_token) else {
+            return;
+        };
+
+        let address = match address.parse() {
+            Ok(x) => x,
+            Err(e) => {
+                error!("invalid admin API address '{address}': {e}");
+                return;
+            }
+        };
+
+        crate::tokio::spawn(crate::admin_api::run(address, token.clone(), server.make_weak()));
+    }
+
+    /// Toggles whether new UDP connections are accepted, existing connections are kept
+    #[cfg(feature = "admin-api")]
+    pub fn set_udp_connections_enabled(&self, enabled: bool) {
+        info!("{} new UDP connections", if enabled { "Accepting" } else { "Refusing" });
+        self.udp_connections_enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "admin-api")]
+    pub fn session_summaries(&self) -> Vec<crate::session_manager::SessionSummary> {
+        self.session_manager.summaries()
+    }
+
+    /// Writes a binary snapshot of a session to `snapshot_dir`, returns `None` if the session does not exist
+    #[cfg(feature = "admin-api")]
+    pub async fn write_session_snapshot(
+        &self,
+        session_id: u64,
+    ) -> Option<std::io::Result<std::path::PathBuf>> {
+        let mut writer = server_shared::qunet::buffers::HeapByteWriter::new();
+        self.session_manager.find(session_id)?.write_snapshot(&mut writer);
+
+        // relative paths resolve against the working directory
+        let dir = self.config.load().snapshot_dir.clone();
+        let path = dir.join(format!("{}-{}.bin", format_systime(SystemTime::now()), session_id));
+
+        let result = match crate::tokio::fs::create_dir_all(&dir).await {
+            Ok(()) => crate::tokio::fs::write(&path, writer.written()).await,
+            Err(e) => Err(e),
+        };
+
+        Some(result.map(|_| path))
+    }
+
+    /// Writes a line about a moderation action to the log, in a stable `key=value` format that is easy to search.
+    /// Only called for actions that actually happened.
+    pub fn audit_log(&self, actor: AuditActor, action: &str, target: i32, reason: &str) {
+        info!(
+            target: "audit",
+            "audit time={} actor={actor} action={action} target={target} reason={reason:?}",
+            format_systime(SystemTime::now())
+        );
+    }
+
+    /// Disconnects a connected user, returns false if they aren't connected
+    pub fn kick_player(&self, actor: AuditActor, account_id: i32, reason: &'static str) -> bool {
+        let Some(client) = self.find_client(account_id) else {
+            return false;
+        };
+
+        self.audit_log(actor, "kick", account_id, reason);
+        client.disconnect(reason);
+
+        true
+    }
+
+    /// Sends an announcement to all players that are in a session, returns the number of sessions it was sent to
+    pub fn broadcast_announcement(&self, text: &str) -> usize {
+        let sessions = self.session_manager.all_sessions();
+
+        let event = self.to_owned_event(
+            &AnnouncementEvent { text },
+            Some(EventOptions {
+                reliable: true,
+                ..Default::default()
+            }),
+        );
+
+        for session in &sessions {
+            session.push_event_to_all(event.clone());
+        }
+
+        info!("Sent announcement to {} sessions: {text}", sessions.len());
+
+        sessions.len()
+    }
+
+    /// Freezes or unfreezes a connected user, returns false if they aren't connected.
+    /// The state isn't persisted anywhere, so it clears once the user reconnects.
+    pub fn set_user_frozen(&self, actor: AuditActor, account_id: i32, frozen: bool) -> bool {
+        let Some(client) = self.find_client(account_id) else {
+            return false;
+        };
+
+        self.audit_log(actor, if frozen { "freeze" } else { "unfreeze" }, account_id, "");
+
+        client.set_frozen(frozen);
+
+        if let Some(session) = client.session() {
+            session.set_frozen(account_id, frozen);
+        }
+
+        true
+    }
+
+    pub fn version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+
+    pub fn build_date() -> &'static str {
+        build_time_utc!("%Y-%m-%dT%H:%M:%S")
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Updates the player size estimate from the size of an encoded level data message.
+    /// Messages with metadata responses are skipped, those are sized exactly when resolving the requests.
+    fn record_level_data_size(
+        &self,
+        total: usize,
+        event_len: usize,
+        players: usize,
+        requests: usize,
+    ) {
+        const SMOOTHING: f32 = 0.05;
+
+        if requests > 0 || players == 0 {
+            return;
+        }
+
+        let sample = total.saturating_sub(event_len + 32) as f32 / players as f32;
+
+        let old = self.player_size_estimate.load(Ordering::Relaxed);
+        let new = if old == 0.0 { sample } else { old + (sample - old) * SMOOTHING };
+        self.player_size_estimate.store(new, Ordering::Relaxed);
+    }
+
     pub fn get_status_data(&self) -> SrvStatusData {
         self.cached_status_data.lock().clone()
     }
@@ -1415,6 +2769,15 @@ impl ConnectionHandler {
                 return;
             }
         };
+        self.session_manager.set_max_sessions(config.max_sessions);
+        self.session_manager.set_max_sessions_per_owner(config.max_sessions_per_owner);
+        self.session_manager.set_empty_grace_secs(config.empty_session_grace_secs);
+        self.session_manager.set_queue_limits(QueueLimits {
+            max_events: config.max_queued_events,
+            max_counters: config.max_queued_counters,
+        });
+        self.allowed_accounts.store(Arc::new(build_allowlist(&config)));
+        self.banned_ips.store(Arc::new(IpBanList::from_entries(&config.banned_ips)));
         self.config.store(Arc::new(config));
 
         if let Some(calc) = self.load_calculator.as_ref() {
@@ -1446,6 +2809,80 @@ impl ConnectionHandler {
     }
 }
 
+fn build_allowlist(config: &Config) -> Option<HashSet<i32>> {
+    config
+        .allowed_account_ids
+        .as_ref()
+        .filter(|ids| !ids.is_empty())
+        .map(|ids| ids.iter().copied().collect())
+}
+
+/// Upper bound of the size of an empty display data response, for players that were not found
+const MISSING_DISPLAY_DATA_BYTES: usize = 16;
+/// Upper bound of the size of a display data response without the variable length fields
+const DISPLAY_DATA_BASE_BYTES: usize = 64;
+
+/// Upper bound of the encoded size of a display data response about `client`
+fn display_data_size(client: &ClientStateHandle) -> usize {
+    let Some(adata) = client.account_data() else {
+        return MISSING_DISPLAY_DATA_BYTES;
+    };
+
+    let special = client.special_data();
+    let special_size =
+        special.as_ref().as_ref().map_or(0, |d| 16 + d.roles.len() + client.name_color_size());
+
+    DISPLAY_DATA_BASE_BYTES + adata.username.len() + special_size
+}
+
+/// Returns the estimated size with some headroom, or `fallback` if there is no estimate yet
+fn size_estimate(estimate: &AtomicF32, fallback: usize) -> usize {
+    let value = estimate.load(Ordering::Relaxed);
+
+    if value > 0.0 { (value * 1.25).ceil() as usize } else { fallback }
+}
+
+/// Collects the bearings and distances to the closest visible players, relative to `center`
+fn build_radar(
+    snapshot: &TickSnapshot,
+    account_id: i32,
+    center: &Point,
+    is_mod: bool,
+) -> RadarEvent {
+    let mut entries: Vec<RadarEntry> = snapshot
+        .players
+        .iter()
+        .filter(|p| p.state.account_id != account_id && !p.stale && (is_mod || !p.wants_hidden))
+        .filter_map(|p| {
+            let position = p.state.player1().position;
+            let distance = center.distance(&position);
+
+            (distance <= RADAR_MAX_DISTANCE).then(|| RadarEntry {
+                account_id: p.state.account_id,
+                angle: center.angle_to(&position),
+                distance,
+            })
+        })
+        .collect();
+
+    entries.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    entries.truncate(RADAR_MAX_PLAYERS);
+
+    RadarEvent {
+        entries: entries.into_iter().collect(),
+    }
+}
+
+/// Returns the combined permissions of the given roles, and whether any of them can moderate
+fn resolve_role_permissions(server_roles: &[ServerRole], roles: &[u8]) -> (RolePermissions, bool) {
+    server_roles
+        .iter()
+        .filter(|sr| roles.contains(&sr.id))
+        .fold((RolePermissions::NONE, false), |(perms, moderator), sr| {
+            (perms | sr.permissions, moderator || sr.can_moderate)
+        })
+}
+
 fn must_auth(client: &ClientState<ConnectionHandler>) -> HandlerResult<()> {
     if client.data().authorized() {
         Ok(())
@@ -1488,6 +2925,51 @@ fn decode_script_array<'a>(
     Ok(scripts)
 }
 
+/// Checks the upload against the configured limits, that exactly one script is the main one,
+/// and that filenames can't be mistaken for paths. `MAX_SCRIPT_COUNT` is already enforced when decoding.
+#[cfg_attr(not(feature = "scripting"), allow(unused))]
+fn validate_scripts(
+    scripts: &[BorrowedLevelScript<'_>],
+    max_count: usize,
+    max_bytes: usize,
+) -> Result<(), ScriptRejectReason> {
+    if scripts.len() > max_count {
+        return Err(ScriptRejectReason::TooManyScripts(scripts.len(), max_count));
+    }
+
+    let total_bytes = scripts.iter().map(|s| s.content.len()).sum::<usize>();
+    if total_bytes > max_bytes {
+        return Err(ScriptRejectReason::TooLarge(total_bytes, max_bytes));
+    }
+
+    match scripts.iter().filter(|s| s.main).count() {
+        0 => return Err(ScriptRejectReason::NoMainScript),
+        1 => {}
+        n => return Err(ScriptRejectReason::MultipleMainScripts(n)),
+    }
+
+    for script in scripts {
+        let name = script.filename;
+
+        if name.is_empty() {
+            return Err(ScriptRejectReason::EmptyFilename);
+        }
+
+        if name.len() > MAX_SCRIPT_FILENAME_LEN {
+            return Err(ScriptRejectReason::FilenameTooLong);
+        }
+
+        let valid_chars =
+            name.bytes().all(|c| c.is_ascii_alphanumeric() || matches!(c, b'_' | b'-' | b'.'));
+
+        if !valid_chars || name.starts_with('.') || name.contains("..") {
+            return Err(ScriptRejectReason::InvalidFilename(name.to_owned()));
+        }
+    }
+
+    Ok(())
+}
+
 fn format_systime(s: SystemTime) -> String {
     time_format::strftime_utc(
         "%Y-%m-%dT%H.%M.%S",
@@ -1539,3 +3021,39 @@ async fn dump_connection_data(conn: &FinishedConnection, dir: &Path) -> std::io:
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(filename: &str, main: bool) -> BorrowedLevelScript<'_> {
+        BorrowedLevelScript {
+            content: "",
+            filename,
+            main,
+            signature: [0; 32],
+        }
+    }
+
+    #[test]
+    fn scripts_need_a_main_script() {
+        let scripts = [script("a.lua", false), script("b.lua", false)];
+
+        assert!(matches!(
+            validate_scripts(&scripts, 8, 1024),
+            Err(ScriptRejectReason::NoMainScript)
+        ));
+    }
+
+    #[test]
+    fn scripts_need_exactly_one_main_script() {
+        let scripts = [script("a.lua", true), script("b.lua", false), script("c.lua", true)];
+
+        assert!(matches!(
+            validate_scripts(&scripts, 8, 1024),
+            Err(ScriptRejectReason::MultipleMainScripts(2))
+        ));
+
+        assert!(validate_scripts(&scripts[..2], 8, 1024).is_ok());
+    }
+}
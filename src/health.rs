@@ -0,0 +1,97 @@
+//! Health check endpoint for load balancers and orchestrators.
+//!
+//! Answers every HTTP request with `200 ready` once the server is logged in to the central server,
+//! and `503 starting` or `503 degraded` otherwise, since players can't be validated without the central server.
+
+use std::{net::SocketAddr, time::Duration};
+
+use server_shared::qunet::server::WeakServerHandle;
+use tracing::{debug, error, info, warn};
+
+use crate::{
+    handler::ConnectionHandler,
+    tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    },
+};
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthState {
+    /// Connected and logged in to the central server
+    Ready,
+    /// Has not finished logging in to the central server since launch
+    Starting,
+    /// Was ready before, but lost the connection to the central server or failed to log in again
+    Degraded,
+}
+
+impl HealthState {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Ready => "ready",
+            Self::Starting => "starting",
+            Self::Degraded => "degraded",
+        }
+    }
+}
+
+pub async fn run(address: SocketAddr, server: WeakServerHandle<ConnectionHandler>) {
+    let listener = match TcpListener::bind(address).await {
+        Ok(x) => x,
+        Err(e) => {
+            error!("failed to bind the health check to {address}: {e}");
+            return;
+        }
+    };
+
+    info!("- Health check listening on: {address}");
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(x) => x,
+            Err(e) => {
+                warn!("health check failed to accept a connection: {e}");
+                continue;
+            }
+        };
+
+        let server = server.clone();
+
+        crate::tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &server).await {
+                debug!("health check connection from {peer} failed: {e}");
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    server: &WeakServerHandle<ConnectionHandler>,
+) -> std::io::Result<()> {
+    // the request itself doesn't matter, but read it so the client doesn't see a reset
+    let mut buf = [0u8; 1024];
+    let _ = crate::tokio::time::timeout(READ_TIMEOUT, stream.read(&mut buf)).await;
+
+    let state = match server.upgrade() {
+        Some(server) => server.handler().health_state(),
+        None => HealthState::Degraded,
+    };
+
+    let (status, reason) = match state {
+        HealthState::Ready => (200, "OK"),
+        _ => (503, "Service Unavailable"),
+    };
+
+    let body = state.as_str();
+    let out = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+
+    stream.write_all(out.as_bytes()).await?;
+    stream.shutdown().await
+}
@@ -0,0 +1,104 @@
+use std::net::IpAddr;
+
+use rustc_hash::FxHashSet;
+use thiserror::Error;
+use tracing::warn;
+
+#[derive(Debug, Error)]
+pub enum IpBanParseError {
+    #[error("invalid IP address: {0}")]
+    InvalidAddress(String),
+    #[error("invalid prefix length: {0}")]
+    InvalidPrefix(String),
+}
+
+/// A CIDR range, e.g. `10.0.0.0/8`
+struct IpRange {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl IpRange {
+    fn contains(&self, addr: IpAddr) -> bool {
+        match (self.network, addr) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                (u32::from(net) & mask) == (u32::from(addr) & mask)
+            }
+
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                (u128::from(net) & mask) == (u128::from(addr) & mask)
+            }
+
+            _ => false,
+        }
+    }
+}
+
+/// Set of banned IP addresses and CIDR ranges
+#[derive(Default)]
+pub struct IpBanList {
+    exact: FxHashSet<IpAddr>,
+    ranges: Vec<IpRange>,
+}
+
+impl IpBanList {
+    /// Builds the list from config entries, entries that fail to parse are skipped with a warning.
+    pub fn from_entries(entries: &[String]) -> Self {
+        let mut list = Self::default();
+
+        for entry in entries {
+            if let Err(e) = list.insert(entry) {
+                warn!("ignoring banned IP entry '{entry}': {e}");
+            }
+        }
+
+        list
+    }
+
+    /// Adds an exact address (`1.2.3.4`) or a CIDR range (`1.2.3.0/24`) to the list
+    pub fn insert(&mut self, entry: &str) -> Result<(), IpBanParseError> {
+        let entry = entry.trim();
+
+        let Some((addr, prefix)) = entry.split_once('/') else {
+            let addr = parse_addr(entry)?;
+            self.exact.insert(addr);
+            return Ok(());
+        };
+
+        let network = parse_addr(addr)?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix = prefix
+            .parse::<u8>()
+            .ok()
+            .filter(|p| *p <= max_prefix)
+            .ok_or_else(|| IpBanParseError::InvalidPrefix(entry.to_owned()))?;
+
+        if prefix == max_prefix {
+            self.exact.insert(network);
+        } else {
+            self.ranges.push(IpRange { network, prefix });
+        }
+
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exact.is_empty() && self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        // treat ipv4-mapped ipv6 addresses the same as plain ipv4
+        let addr = addr.to_canonical();
+
+        self.exact.contains(&addr) || self.ranges.iter().any(|r| r.contains(addr))
+    }
+}
+
+fn parse_addr(s: &str) -> Result<IpAddr, IpBanParseError> {
+    s.parse::<IpAddr>()
+        .map(|a| a.to_canonical())
+        .map_err(|_| IpBanParseError::InvalidAddress(s.to_owned()))
+}
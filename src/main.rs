@@ -1,7 +1,7 @@
 #![feature(try_blocks, thread_local, generic_const_exprs)]
 #![allow(clippy::new_without_default, clippy::collapsible_if)]
 
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 
 use self::tokio::io::{AsyncReadExt, AsyncWriteExt};
 use server_shared::{config::parse_addr, data::GameServerData, logging::setup_logger};
@@ -29,6 +29,8 @@ pub use tokio;
 #[cfg(feature = "tokio_tracing")]
 pub use tokio_tracing as tokio;
 
+#[cfg(feature = "admin-api")]
+pub mod admin_api;
 pub mod bridge;
 pub mod client_data;
 pub mod client_store;
@@ -36,15 +38,24 @@ pub mod config;
 pub mod data;
 pub mod events;
 pub mod handler;
+pub mod health;
+pub mod ip_ban;
 pub mod load_calculator;
+pub mod observer;
 pub mod player_state;
 
 #[cfg(feature = "scripting")]
 pub mod scripting;
 pub mod session_manager;
+// only written through the admin API, but the format is always built so its tests run
+#[cfg_attr(not(feature = "admin-api"), allow(unused))]
+pub mod session_snapshot;
+pub mod spatial_index;
 pub mod trigger_manager;
 pub mod util;
 pub mod voice_message;
+#[cfg(feature = "voice-processing")]
+pub mod voice_processor;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -70,6 +81,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
+    if let Some(addr) = &config.bridge_bind_address {
+        error!(
+            "bridge_bind_address is set to '{addr}', but the qunet client cannot bind to a local address yet."
+        );
+        error!(
+            "Remove the option, or use a source-based route to pick the interface used for the central server."
+        );
+        return Ok(());
+    }
+
     let tcp_address = config.tcp.enable.then(|| parse_addr(&config.tcp.address, "tcp_address"));
 
     let udp_address = config.udp.enable.then(|| parse_addr(&config.udp.address, "udp_address"));
@@ -78,23 +99,50 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let server_address = if let Some(addr) = &config.server_address {
         addr.clone()
     } else {
-        let ip = find_my_ip_address().await?;
-        if let Some(addr) = &udp_address {
-            format!("udp://{ip}:{}", addr.port())
+        let (scheme, bind_addr) = if let Some(addr) = &udp_address {
+            ("udp", addr)
         } else if let Some(addr) = &tcp_address {
-            format!("tcp://{ip}:{}", addr.port())
+            ("tcp", addr)
         } else {
             error!("Both TCP and UDP are disabled, server cannot launch!");
             return Ok(());
-        }
+        };
+
+        let ip = if config.disable_ip_discovery {
+            if bind_addr.ip().is_unspecified() {
+                error!(
+                    "IP discovery is disabled, but the server is bound to a wildcard address ({bind_addr}) that can't be advertised."
+                );
+                error!(
+                    "Set server_address, or bind to the address that clients should connect to."
+                );
+                return Ok(());
+            }
+
+            bind_addr.ip()
+        } else {
+            find_my_ip_address().await?
+        };
+
+        // formatting as a socket address puts brackets around ipv6 addresses
+        format!("{scheme}://{}", SocketAddr::new(ip, bind_addr.port()))
+    };
+
+    let (Some(string_id), Some(name), Some(region), Some(address)) = (
+        bounded_str(&config.server_id, "server_id"),
+        bounded_str(&config.server_name, "server_name"),
+        bounded_str(&config.server_region, "server_region"),
+        bounded_str(&server_address, "server_address"),
+    ) else {
+        return Ok(());
     };
 
     let data = GameServerData {
         id: 0,
-        string_id: config.server_id.as_str().try_into().expect("server_id is too long"),
-        name: config.server_name.as_str().try_into().expect("server_name is too long"),
-        region: config.server_region.as_str().try_into().expect("server_region is too long"),
-        address: server_address.as_str().try_into().expect("server_address is too long"),
+        string_id,
+        name,
+        region,
+        address,
     };
 
     let handler = ConnectionHandler::new(config.clone(), data).await;
@@ -151,6 +199,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Converts a config string into a fixed capacity string, printing an error if it does not fit
+fn bounded_str<const N: usize>(value: &str, field: &str) -> Option<heapless::String<N>> {
+    let out = value.try_into().ok();
+
+    if out.is_none() {
+        error!(
+            "{field} is too long ({} bytes, maximum is {N}), please shorten it in the config file.",
+            value.len()
+        );
+    }
+
+    out
+}
+
 fn make_memory_limits(usage: u32) -> MemoryUsageOptions {
     let (initial_mem, max_mem, rcvbuf, sndbuf) = server_shared::config::make_memory_limits(usage);
 
@@ -0,0 +1,113 @@
+//! Optional stream of domain events (joins, leaves, session lifecycle) for external monitoring services.
+//! The observer only ever receives data, it has no way to influence the server.
+
+use std::{fmt, time::Duration};
+
+use tracing::{debug, info, warn};
+
+use crate::tokio::{
+    io::AsyncWriteExt,
+    net::TcpStream,
+    sync::mpsc::{self, error::TrySendError},
+};
+
+/// Events that are queued while the observer is unreachable, newer events are dropped past this
+const MAX_QUEUED_EVENTS: usize = 4096;
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug)]
+pub enum ObserverEvent {
+    SessionCreated {
+        session: u64,
+        owner: i32,
+    },
+    SessionDeleted {
+        session: u64,
+    },
+    PlayerJoin {
+        session: u64,
+        account_id: i32,
+    },
+    PlayerLeave {
+        session: u64,
+        account_id: i32,
+    },
+}
+
+impl fmt::Display for ObserverEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SessionCreated { session, owner } => {
+                write!(f, "session-created {session} {owner}")
+            }
+            Self::SessionDeleted { session } => write!(f, "session-deleted {session}"),
+            Self::PlayerJoin { session, account_id } => write!(f, "join {session} {account_id}"),
+            Self::PlayerLeave { session, account_id } => write!(f, "leave {session} {account_id}"),
+        }
+    }
+}
+
+pub trait ObserverSink: Send + Sync {
+    /// Called for every event, must not block.
+    fn emit(&self, event: ObserverEvent);
+}
+
+/// Sends events as newline separated text over a TCP connection, reconnecting if it drops
+pub struct TcpObserverSink {
+    tx: mpsc::Sender<ObserverEvent>,
+}
+
+impl TcpObserverSink {
+    /// Starts the background connection task, must be called from within the runtime
+    pub fn spawn(address: String) -> Self {
+        let (tx, rx) = mpsc::channel(MAX_QUEUED_EVENTS);
+        crate::tokio::spawn(run(address, rx));
+
+        Self { tx }
+    }
+}
+
+impl ObserverSink for TcpObserverSink {
+    fn emit(&self, event: ObserverEvent) {
+        if let Err(TrySendError::Full(event)) = self.tx.try_send(event) {
+            debug!("observer queue is full, dropping event: {event}");
+        }
+    }
+}
+
+async fn run(address: String, mut rx: mpsc::Receiver<ObserverEvent>) {
+    let mut pending: Option<ObserverEvent> = None;
+
+    loop {
+        let mut stream = match TcpStream::connect(&address).await {
+            Ok(s) => {
+                info!("Connected to the observer at {address}");
+                s
+            }
+
+            Err(e) => {
+                warn!("Failed to connect to the observer at {address}: {e}");
+                crate::tokio::time::sleep(RECONNECT_DELAY).await;
+                continue;
+            }
+        };
+
+        loop {
+            let event = match pending.take() {
+                Some(ev) => ev,
+                None => match rx.recv().await {
+                    Some(ev) => ev,
+                    None => return, // server shutting down
+                },
+            };
+
+            if let Err(e) = stream.write_all(format!("{event}\n").as_bytes()).await {
+                warn!("Lost connection to the observer: {e}");
+                pending = Some(event);
+                break;
+            }
+        }
+
+        crate::tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
@@ -26,14 +26,25 @@ impl Point {
     }
 
     /// Calculates angle to another point in radians, with 0 meaning right and positive means CCW rotation
-    /// Range is [0, 2pi)
+    /// Range is [0, 2pi), if both points are the same (or either is NaN) the angle is 0
     pub fn angle_to(&self, other: &Point) -> f32 {
         let dy = other.y - self.y;
         let dx = other.x - self.x;
+
+        // atan2 of (±0, -0) is ±pi, pick a stable direction instead
+        if (dx == 0.0 && dy == 0.0) || dx.is_nan() || dy.is_nan() {
+            return 0.0;
+        }
+
         let mut angle = dy.atan2(dx);
 
         if angle < 0.0 {
             angle += std::f32::consts::TAU;
+
+            // tiny negative angles round up to exactly 2pi, which is the same direction as 0
+            if angle >= std::f32::consts::TAU {
+                angle = 0.0;
+            }
         }
 
         debug_assert!((0.0..std::f32::consts::TAU).contains(&angle));
@@ -335,11 +346,13 @@ impl PlayerState {
         })
     }
 
+    /// Encodes the player state. If `force_culled` is true, the player is encoded as culled regardless of the camera range.
     pub fn encode(
         &self,
         mut builder: player_data::Builder<'_>,
         platformer: bool,
         camera_range: &CameraRange,
+        force_culled: bool,
     ) {
         builder.set_account_id(self.account_id);
         builder.set_timestamp(self.timestamp);
@@ -357,14 +370,14 @@ impl PlayerState {
             let angle = self.angle_to(camera_range);
 
             // map it to a value between 0 and 65535
-            let perc = (angle / std::f32::consts::TAU * 65535.0) as u16;
+            let perc = (angle / std::f32::consts::TAU * 65535.0).clamp(0.0, 65535.0) as u16;
             builder.set_percentage(perc);
         } else {
             // in classic levels, just send over the percentage as calculated by that client
             builder.set_percentage(self.percentage);
         }
 
-        if self.in_range(camera_range) {
+        if !force_culled && self.in_range(camera_range) {
             match &self.data_kind {
                 PlayerDataKind::Single { player } => {
                     player.encode(builder.init_single().init_player1());
@@ -418,6 +431,19 @@ impl CameraRange {
             radius,
         }
     }
+
+    pub fn center(&self) -> &Point {
+        &self.center
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Returns the same range with the radius multiplied by `factor`
+    pub fn scaled(&self, factor: f32) -> Self {
+        Self::new(self.center.x, self.center.y, self.radius * factor)
+    }
 }
 
 #[derive(Default, Clone, Copy)]
@@ -436,3 +462,32 @@ impl PlayerLevelMeta {
         builder.set_progress(self.progress);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    #[test]
+    fn angle_between_coincident_points_is_zero() {
+        let p = Point::new(12.5, -3.0);
+        assert_eq!(p.angle_to(&p), 0.0);
+
+        // -0 - 0 must not turn into an angle of pi
+        assert_eq!(Point::new(0.0, 0.0).angle_to(&Point::new(-0.0, -0.0)), 0.0);
+        assert_eq!(Point::new(0.0, 0.0).angle_to(&Point::new(f32::NAN, 1.0)), 0.0);
+    }
+
+    #[test]
+    fn angle_near_tau_stays_in_range() {
+        let origin = Point::new(0.0, 0.0);
+
+        // rounds up to exactly 2pi when shifted into range, which wraps around to 0
+        assert_eq!(origin.angle_to(&Point::new(1.0, -1e-9)), 0.0);
+
+        let angle = origin.angle_to(&Point::new(1.0, -1e-3));
+        assert!(angle > TAU - 0.01 && angle < TAU);
+
+        assert!((origin.angle_to(&Point::new(0.0, 1.0)) - TAU / 4.0).abs() < 1e-6);
+    }
+}
@@ -2,26 +2,36 @@ use std::sync::OnceLock;
 use std::{
     collections::VecDeque,
     hash::Hash,
-    sync::{Arc, Weak},
-    time::Instant,
+    num::NonZero,
+    sync::{
+        Arc, Weak,
+        atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
-use dashmap::DashMap;
+use arc_swap::ArcSwapOption;
+use dashmap::{DashMap, mapref::entry::Entry};
 use nohash_hasher::BuildNoHashHasher;
 use parking_lot::Mutex;
 use rustc_hash::{FxHashMap, FxHashSet};
-use server_shared::events::{EventOptions, OwnedEvent};
+use server_shared::events::{EventOptions, EventStringCache, OwnedEvent};
 use server_shared::qunet::server::{ServerHandle, WeakServerHandle};
 use smallvec::SmallVec;
-use tracing::trace;
+use thiserror::Error;
+use tracing::{info, trace};
 
 use crate::util::{iter_dashmap, iter_dashmap_mut};
 use crate::{
     events::*,
     handler::{ConnectionHandler, MAX_EVENT_COUNT},
-    player_state::{PlayerLevelMeta, PlayerState},
+    observer::{ObserverEvent, ObserverSink},
+    player_state::{PlayerLevelMeta, PlayerState, Point},
+    spatial_index::SpatialGrid,
     trigger_manager::TriggerManager,
 };
+#[cfg(feature = "admin-api")]
+use {crate::session_snapshot::SessionSnapshot, server_shared::qunet::buffers::HeapByteWriter};
 #[cfg(feature = "scripting")]
 use {
     crate::{
@@ -29,24 +39,132 @@ use {
         scripting::{LuaCompilerError, ScriptManager},
     },
     server_shared::SessionId,
-    thiserror::Error,
 };
 
+/// Tick snapshots of sessions with at least this many players come with a spatial index of player positions
+const SPATIAL_INDEX_MIN_PLAYERS: usize = 50;
+const SPATIAL_INDEX_CELL_SIZE: f32 = 512.0;
+
+/// Sessions that never had more players than this don't bother shrinking their maps
+const SHRINK_MIN_PEAK_PLAYERS: usize = 64;
+/// Maps are shrunk once the player count falls below 1/N of the peak
+const SHRINK_PEAK_FRACTION: usize = 4;
+
+/// Upper bounds of the session lifetime buckets, the last bucket holds everything above the last bound
+pub const SESSION_LIFETIME_BUCKETS: [Duration; 4] = [
+    Duration::from_mins(1),
+    Duration::from_mins(10),
+    Duration::from_hours(1),
+    Duration::from_hours(6),
+];
+
+#[derive(Default)]
+pub struct SessionStats {
+    pub total_created: AtomicU64,
+    pub lifetimes: [AtomicU64; SESSION_LIFETIME_BUCKETS.len() + 1],
+    /// Events and counter changes dropped because a player's queue was full
+    pub dropped_events: AtomicU64,
+}
+
+impl SessionStats {
+    fn record_lifetime(&self, lifetime: Duration) {
+        let bucket = SESSION_LIFETIME_BUCKETS
+            .iter()
+            .position(|b| lifetime < *b)
+            .unwrap_or(SESSION_LIFETIME_BUCKETS.len());
+
+        self.lifetimes[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Limits on how much can be queued for a single player in a session
+#[derive(Clone, Copy, Debug)]
+pub struct QueueLimits {
+    pub max_events: usize,
+    pub max_counters: usize,
+}
+
+#[cfg(feature = "admin-api")]
+pub struct SessionSummary {
+    pub id: u64,
+    pub player_count: usize,
+    pub owner: i32,
+    pub editor_collab: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum SessionCreateError {
+    #[error("maximum session count reached")]
+    LimitReached,
+    #[error("room owner {0} has too many sessions")]
+    OwnerLimitReached(i32),
+}
+
 pub struct SessionManager {
     sessions: DashMap<u64, Arc<GameSession>>,
     ec_sessions: DashMap<u64, Arc<GameSession>>,
     server: OnceLock<WeakServerHandle<ConnectionHandler>>,
+    /// Number of existing sessions, incremented before a session is inserted so that the limit cannot be exceeded
+    session_count: AtomicUsize,
+    max_sessions: AtomicUsize,
+    /// Number of existing sessions in rooms owned by each account, accounts with no sessions are removed
+    owner_sessions: DashMap<i32, usize, BuildNoHashHasher<i32>>,
+    max_sessions_per_owner: AtomicUsize,
+    /// How long empty sessions are kept before `sweep_empty_sessions` deletes them, in seconds
+    empty_grace_secs: AtomicU64,
+    queue_limits: Mutex<QueueLimits>,
+    stats: SessionStats,
+    observer: Option<Box<dyn ObserverSink>>,
 }
 
 impl SessionManager {
-    pub fn new() -> Self {
+    /// Creates a new session manager, `max_sessions` of 0 means there is no limit on the amount of sessions.
+    pub fn new(
+        max_sessions: usize,
+        max_sessions_per_owner: usize,
+        empty_grace_secs: u64,
+        queue_limits: QueueLimits,
+        observer: Option<Box<dyn ObserverSink>>,
+    ) -> Self {
         Self {
             sessions: DashMap::new(),
             ec_sessions: DashMap::new(),
             server: OnceLock::new(),
+            session_count: AtomicUsize::new(0),
+            max_sessions: AtomicUsize::new(max_sessions),
+            owner_sessions: DashMap::default(),
+            max_sessions_per_owner: AtomicUsize::new(max_sessions_per_owner),
+            empty_grace_secs: AtomicU64::new(empty_grace_secs),
+            queue_limits: Mutex::new(queue_limits),
+            stats: SessionStats::default(),
+            observer,
+        }
+    }
+
+    /// Passes an event to the observer, if one is configured
+    pub fn observe(&self, event: ObserverEvent) {
+        if let Some(observer) = &self.observer {
+            observer.emit(event);
         }
     }
 
+    pub fn set_max_sessions(&self, max_sessions: usize) {
+        self.max_sessions.store(max_sessions, Ordering::Relaxed);
+    }
+
+    pub fn set_max_sessions_per_owner(&self, max_sessions: usize) {
+        self.max_sessions_per_owner.store(max_sessions, Ordering::Relaxed);
+    }
+
+    pub fn set_empty_grace_secs(&self, secs: u64) {
+        self.empty_grace_secs.store(secs, Ordering::Relaxed);
+    }
+
+    /// Sets the queue limits for sessions created from now on, existing sessions keep their limits.
+    pub fn set_queue_limits(&self, limits: QueueLimits) {
+        *self.queue_limits.lock() = limits;
+    }
+
     pub fn init_server(&self, handle: WeakServerHandle<ConnectionHandler>) {
         let _ = self.server.set(handle);
     }
@@ -55,38 +173,186 @@ impl SessionManager {
         self.server.get().expect("server not initialized").upgrade().expect("server destroyed")
     }
 
+    /// Returns the session with the given ID, creating it if it doesn't exist yet.
+    /// With `bypass_limits`, a new session is created even if `max_sessions` or the owner's limit is reached.
     pub fn get_or_create_session(
         self: &Arc<SessionManager>,
         session_id: u64,
         owner: i32,
         platformer: bool,
         editor_collab: bool,
-    ) -> Arc<GameSession> {
+        bypass_limits: bool,
+    ) -> Result<Arc<GameSession>, SessionCreateError> {
         let map = if editor_collab { &self.ec_sessions } else { &self.sessions };
 
-        map.entry(session_id)
-            .or_insert_with(|| GameSession::new(session_id, owner, platformer, editor_collab, self))
-            .clone()
+        // the entry holds the shard lock, so the same session cannot be created twice,
+        // and reserving a slot before inserting guarantees that concurrent creations can't go over the limit
+        match map.entry(session_id) {
+            Entry::Occupied(e) => Ok(e.get().clone()),
+
+            Entry::Vacant(e) => {
+                self.reserve_owner_slot(owner, bypass_limits)?;
+
+                if let Err(err) = self.reserve_session_slot(bypass_limits) {
+                    self.release_owner_slot(owner);
+                    return Err(err);
+                }
+
+                self.stats.total_created.fetch_add(1, Ordering::Relaxed);
+
+                let session = GameSession::new(session_id, owner, platformer, editor_collab, self);
+                self.observe(ObserverEvent::SessionCreated { session: session_id, owner });
+
+                Ok(e.insert(session).clone())
+            }
+        }
+    }
+
+    fn reserve_session_slot(&self, bypass_limit: bool) -> Result<(), SessionCreateError> {
+        let max = if bypass_limit {
+            0
+        } else {
+            self.max_sessions.load(Ordering::Relaxed)
+        };
+
+        self.session_count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                (max == 0 || count < max).then_some(count + 1)
+            })
+            .map(|_| ())
+            .map_err(|_| SessionCreateError::LimitReached)
     }
 
+    /// Counts a new session towards the room owner's limit, sessions without an owner are not counted
+    fn reserve_owner_slot(&self, owner: i32, bypass_limit: bool) -> Result<(), SessionCreateError> {
+        if owner == 0 {
+            return Ok(());
+        }
+
+        let max = if bypass_limit {
+            0
+        } else {
+            self.max_sessions_per_owner.load(Ordering::Relaxed)
+        };
+        let mut count = self.owner_sessions.entry(owner).or_insert(0);
+
+        if max != 0 && *count >= max {
+            return Err(SessionCreateError::OwnerLimitReached(owner));
+        }
+
+        *count += 1;
+        Ok(())
+    }
+
+    fn release_owner_slot(&self, owner: i32) {
+        if owner == 0 {
+            return;
+        }
+
+        if let Entry::Occupied(mut e) = self.owner_sessions.entry(owner) {
+            *e.get_mut() -= 1;
+
+            if *e.get() == 0 {
+                e.remove();
+            }
+        }
+    }
+
+    /// Deletes the session if it has no players. With a grace period set, the session is only marked as empty
+    /// and deleted later by `sweep_empty_sessions`, unless someone joins it again before that.
     pub fn delete_session_if_empty(&self, session_id: u64, editor_collab: bool) {
         let map = if editor_collab { &self.ec_sessions } else { &self.sessions };
 
+        if self.empty_grace_secs.load(Ordering::Relaxed) != 0 {
+            if let Some(session) = map.get(&session_id)
+                && session.players.is_empty()
+            {
+                *session.emptied_at.lock() = Some(Instant::now());
+            }
+
+            return;
+        }
+
         if let Some((_, session)) =
             map.remove_if(&session_id, |_, session| session.players.is_empty())
         {
-            #[cfg(feature = "scripting")]
-            if let Some(scripting) = session.scripting() {
-                scripting.cleanup();
+            self.on_session_deleted(session);
+        }
+    }
+
+    /// Deletes sessions that have been empty for longer than the grace period
+    pub fn sweep_empty_sessions(&self) {
+        let grace = Duration::from_secs(self.empty_grace_secs.load(Ordering::Relaxed));
+
+        let expired = |session: &Arc<GameSession>| {
+            session.players.is_empty()
+                && session.emptied_at.lock().is_some_and(|t| t.elapsed() >= grace)
+        };
+
+        for map in [&self.sessions, &self.ec_sessions] {
+            let mut ids = Vec::new();
+
+            iter_dashmap(map, |(id, session)| {
+                if expired(session) {
+                    ids.push(*id);
+                }
+            });
+
+            for session_id in ids {
+                // someone might have joined (and left) in the meantime, so check again
+                if let Some((_, session)) = map.remove_if(&session_id, |_, s| expired(s)) {
+                    self.on_session_deleted(session);
+                }
             }
+        }
+    }
+
+    fn on_session_deleted(&self, session: Arc<GameSession>) {
+        self.session_count.fetch_sub(1, Ordering::AcqRel);
+        self.release_owner_slot(session.room_owner);
+        self.observe(ObserverEvent::SessionDeleted { session: session.id });
+        self.stats.record_lifetime(session.created_at.elapsed());
 
-            let _ = session;
+        #[cfg(feature = "scripting")]
+        if let Some(scripting) = session.scripting() {
+            scripting.cleanup();
         }
     }
 
+    /// Looks up a session by ID, checking regular sessions before editor collab ones
+    #[cfg(feature = "admin-api")]
+    pub fn find(&self, session_id: u64) -> Option<Arc<GameSession>> {
+        self.sessions
+            .get(&session_id)
+            .or_else(|| self.ec_sessions.get(&session_id))
+            .map(|s| s.value().clone())
+    }
+
     pub fn count(&self) -> usize {
         self.sessions.len() + self.ec_sessions.len()
     }
+
+    /// Returns all sessions, including editor collab ones
+    pub fn all_sessions(&self) -> Vec<Arc<GameSession>> {
+        self.sessions.iter().chain(self.ec_sessions.iter()).map(|s| s.value().clone()).collect()
+    }
+
+    #[cfg(feature = "admin-api")]
+    pub fn summaries(&self) -> Vec<SessionSummary> {
+        self.all_sessions()
+            .into_iter()
+            .map(|s| SessionSummary {
+                id: s.id,
+                player_count: s.player_count(),
+                owner: s.owner(),
+                editor_collab: s.editor_collab,
+            })
+            .collect()
+    }
+
+    pub fn stats(&self) -> &SessionStats {
+        &self.stats
+    }
 }
 
 #[cfg(feature = "scripting")]
@@ -110,10 +376,15 @@ pub struct GamePlayerState {
     pub state: PlayerState,
     pub meta: PlayerLevelMeta,
     pub wants_hidden: bool,
+    pub frozen: bool,
+    /// Voice channel the player talks and listens in, 0 is the global channel that reaches and hears everyone
+    pub voice_channel: u16,
 
     unread_counter_values: FxHashMap<u32, UnreadValue>,
     unread_events: VecDeque<OwnedEvent>,
     prio_counter: usize,
+    last_update: Option<Instant>,
+    join_index: u64,
 }
 
 impl GamePlayerState {
@@ -125,12 +396,23 @@ impl GamePlayerState {
             unread_events: VecDeque::new(),
             prio_counter: 0,
             wants_hidden: false,
+            frozen: false,
+            voice_channel: 0,
+            last_update: None,
+            join_index: 0,
         }
     }
 
+    /// Returns whether the player has not sent any state in the given timeout.
+    /// Players that have never sent an update are always considered stale.
+    #[inline]
+    pub fn is_stale(&self, timeout: Duration) -> bool {
+        self.last_update.is_none_or(|t| t.elapsed() > timeout)
+    }
+
     #[inline]
-    pub fn push_event(&mut self, event: OwnedEvent) -> bool {
-        if self.unread_events.len() >= 512 {
+    pub fn push_event(&mut self, event: OwnedEvent, limit: usize) -> bool {
+        if self.unread_events.len() >= limit {
             false
         } else {
             self.unread_events.push_back(event);
@@ -139,14 +421,15 @@ impl GamePlayerState {
     }
 
     #[inline]
-    pub fn push_counter_change(&mut self, item_id: u32, value: i32) {
-        if self.unread_counter_values.len() >= 1024 {
+    pub fn push_counter_change(&mut self, item_id: u32, value: i32, limit: usize) -> bool {
+        if self.unread_counter_values.len() >= limit {
             // u asleep?
-            return;
+            return false;
         }
 
         self.prio_counter = self.prio_counter.wrapping_add(1);
         self.unread_counter_values.insert(item_id, UnreadValue { value, prio: self.prio_counter });
+        true
     }
 
     pub fn pop_counter_changes(&mut self, limit: usize) -> SmallVec<[(u32, i32, usize); 8]> {
@@ -165,24 +448,115 @@ impl GamePlayerState {
     }
 }
 
+/// Copy of a player's state, shared by every recipient of a tick snapshot
+#[derive(Clone, Copy)]
+pub struct PlayerSnapshot {
+    pub state: PlayerState,
+    pub wants_hidden: bool,
+    pub stale: bool,
+}
+
+/// State of all players in a session at a single point in time
+pub struct TickSnapshot {
+    built_at: Instant,
+    pub players: Vec<PlayerSnapshot>,
+    /// Factor every recipient's view distance is multiplied by, `None` if the session is small enough to show everyone
+    pub view_scale: Option<f32>,
+    /// Index of the snapshot positions, only built for large sessions
+    index: Option<SpatialGrid>,
+}
+
+impl TickSnapshot {
+    /// Fills `out` with the IDs of players that may be within `radius` of `center`. Returns `false` if the session
+    /// is too small to have a spatial index, in which case `out` is left empty and the caller should just check every player.
+    /// The result is coarse (grid cells), so it can include players slightly outside the radius.
+    pub fn players_near(&self, center: &Point, radius: f32, out: &mut FxHashSet<i32>) -> bool {
+        out.clear();
+
+        match &self.index {
+            Some(grid) => {
+                grid.query_into(center, radius, out);
+                true
+            }
+
+            None => false,
+        }
+    }
+}
+
+/// Result of a 2-player mode link request
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkRequestOutcome {
+    /// The request was recorded and waits for the target to send a link request back
+    Requested,
+    /// The target had already asked to link with the sender, so the two are now linked
+    Linked,
+    /// One of the players is already linked to someone else
+    Refused,
+}
+
+#[derive(Default)]
+struct PlayerLinks {
+    /// Linked partners, stored in both directions
+    partners: FxHashMap<i32, i32>,
+    /// Requests that were not answered yet, keyed by the requesting player
+    requests: FxHashMap<i32, i32>,
+}
+
+/// Data attached to a session by its owner, sent to players when they join
+pub struct SessionBlob {
+    pub version: u32,
+    pub hash: [u8; 32],
+    pub data: Vec<u8>,
+}
+
+impl SessionBlob {
+    pub fn info_event(&self) -> SessionBlobInfoEvent {
+        SessionBlobInfoEvent {
+            version: self.version,
+            size: self.data.len() as u32,
+            hash: self.hash,
+        }
+    }
+}
+
 pub struct GameSession {
     pub id: u64,
-    pub owner: i32,
     pub platformer: bool,
     pub editor_collab: bool,
 
+    owner: AtomicI32,
+    /// Owner of the room at the time the session was created, unlike `owner` this never changes
+    room_owner: i32,
     players: DashMap<i32, GamePlayerState, BuildNoHashHasher<i32>>,
     player_ids: Mutex<FxHashSet<i32>>,
+    join_counter: AtomicU64,
+    /// Highest player count since the maps were last shrunk
+    peak_players: AtomicUsize,
+    /// Tickrate requested by the session owner, 0 means the server tickrate is used
+    tickrate: AtomicUsize,
+    /// While paused, everyone keeps seeing players where they were when the session was paused
+    paused: AtomicBool,
+    /// Whether view distances are being scaled down because of the player count, only used for logging
+    view_scaled: AtomicBool,
+    queue_limits: QueueLimits,
+    tick_snapshot: ArcSwapOption<TickSnapshot>,
+    links: Mutex<PlayerLinks>,
+    blob: ArcSwapOption<SessionBlob>,
+    /// When the last player left, if the session is waiting to be deleted
+    emptied_at: Mutex<Option<Instant>>,
     triggers: OnceLock<TriggerManager>,
     manager: Weak<SessionManager>,
 
-    #[allow(unused)]
     created_at: Instant,
 
     #[cfg(feature = "scripting")]
     scripting: OnceLock<ScriptManager>,
     #[cfg(feature = "scripting")]
     logs: Mutex<VecDeque<String>>,
+    /// Whether script logs are pushed to the owner as they are produced, instead of waiting to be polled
+    #[cfg(feature = "scripting")]
+    stream_logs: AtomicBool,
 }
 
 impl GameSession {
@@ -195,11 +569,22 @@ impl GameSession {
     ) -> Arc<Self> {
         Arc::new(Self {
             id,
-            owner,
             platformer,
             editor_collab,
+            owner: AtomicI32::new(owner),
+            room_owner: owner,
             players: DashMap::default(),
             player_ids: Mutex::new(FxHashSet::default()),
+            join_counter: AtomicU64::new(0),
+            peak_players: AtomicUsize::new(0),
+            tickrate: AtomicUsize::new(0),
+            paused: AtomicBool::new(false),
+            view_scaled: AtomicBool::new(false),
+            queue_limits: *manager.queue_limits.lock(),
+            tick_snapshot: ArcSwapOption::empty(),
+            links: Mutex::default(),
+            blob: ArcSwapOption::empty(),
+            emptied_at: Mutex::new(None),
             triggers: OnceLock::new(),
             created_at: Instant::now(),
             manager: Arc::downgrade(manager),
@@ -207,9 +592,84 @@ impl GameSession {
             scripting: OnceLock::new(),
             #[cfg(feature = "scripting")]
             logs: Mutex::default(),
+            #[cfg(feature = "scripting")]
+            stream_logs: AtomicBool::new(false),
         })
     }
 
+    /// Returns the account ID of the session owner, or 0 if the session has no owner.
+    #[inline]
+    pub fn owner(&self) -> i32 {
+        self.owner.load(Ordering::Relaxed)
+    }
+
+    /// Returns the tickrate of this session, or `server_tickrate` if the owner never changed it
+    pub fn tickrate(&self, server_tickrate: usize) -> usize {
+        match self.tickrate.load(Ordering::Relaxed) {
+            0 => server_tickrate,
+            x => x.min(server_tickrate),
+        }
+    }
+
+    pub fn blob(&self) -> Option<Arc<SessionBlob>> {
+        self.blob.load_full()
+    }
+
+    /// Replaces the data attached to this session, bumping its version
+    pub fn set_blob(&self, data: Vec<u8>) -> Arc<SessionBlob> {
+        let version = self.blob.load().as_ref().map_or(0, |b| b.version).wrapping_add(1);
+
+        let blob = Arc::new(SessionBlob {
+            version,
+            hash: blake3::hash(&data).into(),
+            data,
+        });
+
+        self.blob.store(Some(blob.clone()));
+        blob
+    }
+
+    pub fn set_tickrate(&self, tickrate: usize) {
+        self.tickrate.store(tickrate, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Pauses or resumes the session, returns the previous state
+    pub fn set_paused(&self, paused: bool) -> bool {
+        self.paused.swap(paused, Ordering::Relaxed)
+    }
+
+    /// If `leaving` is the owner of this session, transfers ownership to the player that has been in the session
+    /// for the longest time. Returns the new owner, if ownership was transferred.
+    pub fn transfer_owner_from(&self, leaving: i32) -> Option<i32> {
+        if leaving == 0 || self.owner() != leaving {
+            return None;
+        }
+
+        let mut next: Option<(u64, i32)> = None;
+
+        iter_dashmap(&self.players, |(id, player)| {
+            if *id != leaving && next.is_none_or(|(idx, _)| player.join_index < idx) {
+                next = Some((player.join_index, *id));
+            }
+        });
+
+        let (_, new_owner) = next?;
+
+        self.owner
+            .compare_exchange(leaving, new_owner, Ordering::Relaxed, Ordering::Relaxed)
+            .ok()?;
+
+        // the new owner did not ask for script logs
+        #[cfg(feature = "scripting")]
+        self.set_stream_logs(false);
+
+        Some(new_owner)
+    }
+
     pub fn triggers(&self) -> &TriggerManager {
         self.triggers.get_or_init(TriggerManager::default)
     }
@@ -253,22 +713,62 @@ impl GameSession {
                 ..Default::default()
             },
             wants_hidden,
+            join_index: self.join_counter.fetch_add(1, Ordering::Relaxed),
             ..Default::default()
         };
 
         if let Some(triggers) = self.triggers.get() {
             iter_dashmap(&triggers.values, |(key, value)| {
-                state.push_counter_change(*key, *value);
+                state.push_counter_change(*key, *value, self.queue_limits.max_counters);
             });
         };
 
         self.players.insert(player_id, state);
         self.player_ids.lock().insert(player_id);
+        self.peak_players.fetch_max(self.players.len(), Ordering::Relaxed);
+
+        // the session is in use again, the grace period starts over when it empties next time
+        *self.emptied_at.lock() = None;
     }
 
     pub fn remove_player(&self, player_id: i32) {
         self.players.remove(&player_id);
         self.player_ids.lock().remove(&player_id);
+
+        self.shrink_if_drained();
+    }
+
+    /// Releases capacity held by the per-session maps after a large room mostly empties out
+    fn shrink_if_drained(&self) {
+        let peak = self.peak_players.load(Ordering::Relaxed);
+        let count = self.players.len();
+
+        if peak < SHRINK_MIN_PEAK_PLAYERS || count * SHRINK_PEAK_FRACTION >= peak {
+            return;
+        }
+
+        // only one caller should do the shrinking
+        if self
+            .peak_players
+            .compare_exchange(peak, count, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+
+        trace!(sid = self.id, "shrinking session maps ({peak} -> {count} players)");
+
+        self.players.shrink_to_fit();
+        self.player_ids.lock().shrink_to_fit();
+
+        if let Some(triggers) = self.triggers.get() {
+            triggers.values.shrink_to_fit();
+        }
+    }
+
+    #[inline]
+    pub fn has_player(&self, player_id: i32) -> bool {
+        self.players.contains_key(&player_id)
     }
 
     #[inline]
@@ -290,7 +790,24 @@ impl GameSession {
         #[cfg(not(feature = "scripting"))]
         let has_scripting = false;
 
-        player.state = state;
+        // out of order updates make interpolation jump backwards, keep the newer state instead
+        let accepted = player.last_update.is_none()
+            || state.timestamp >= player.state.timestamp
+            || !handler.reject_old_timestamps();
+
+        if accepted {
+            player.state = state;
+        } else {
+            trace!(
+                sid = self.id,
+                "dropping update from {} with old timestamp ({} < {})",
+                state.account_id,
+                state.timestamp,
+                player.state.timestamp
+            );
+        }
+
+        player.last_update = Some(Instant::now());
 
         // take some counter values
         let max_counter_values = MAX_EVENT_COUNT.saturating_sub(out_events.len());
@@ -326,6 +843,97 @@ impl GameSession {
         }
     }
 
+    /// Handles a 2-player mode link request from `from` to `to`. The players are only linked once both of them
+    /// have asked to link with each other, and never while either of them is linked to someone else.
+    pub fn request_link(&self, from: i32, to: i32) -> LinkRequestOutcome {
+        let mut links = self.links.lock();
+
+        match (links.partners.get(&from), links.partners.get(&to)) {
+            (None, None) => {}
+            (Some(p), _) if *p == to => return LinkRequestOutcome::Linked,
+            _ => return LinkRequestOutcome::Refused,
+        }
+
+        if links.requests.get(&to) != Some(&from) {
+            links.requests.insert(from, to);
+            return LinkRequestOutcome::Requested;
+        }
+
+        links.requests.remove(&to);
+        links.requests.remove(&from);
+        links.partners.insert(from, to);
+        links.partners.insert(to, from);
+
+        LinkRequestOutcome::Linked
+    }
+
+    /// Removes the 2-player mode link of a player along with any unanswered requests from or to them,
+    /// returning their former partner
+    pub fn unlink_player(&self, account_id: i32) -> Option<i32> {
+        let mut links = self.links.lock();
+
+        links.requests.retain(|from, to| *from != account_id && *to != account_id);
+
+        let partner = links.partners.remove(&account_id)?;
+        links.partners.remove(&partner);
+
+        Some(partner)
+    }
+
+    /// Removes the link of a player that left the session, and tells their former partner about it
+    pub fn unlink_leaving_player(&self, account_id: i32, cache: &EventStringCache) -> Option<i32> {
+        let partner = self.unlink_player(account_id)?;
+
+        let event = OwnedEvent::from_encodable(
+            &TwoPlayerUnlinkEvent,
+            EventOptions {
+                reliable: true,
+                sent_by_player: NonZero::new(account_id),
+                ..Default::default()
+            },
+            cache,
+        );
+        self.push_event(partner, event);
+
+        Some(partner)
+    }
+
+    pub fn set_frozen(&self, account_id: i32, frozen: bool) {
+        if let Some(mut player) = self.players.get_mut(&account_id) {
+            player.frozen = frozen;
+        }
+    }
+
+    pub fn set_voice_channel(&self, account_id: i32, channel: u16) {
+        if let Some(mut player) = self.players.get_mut(&account_id) {
+            player.voice_channel = channel;
+        }
+    }
+
+    /// Returns the players that should hear voice from `sender`, based on their voice channels
+    pub fn voice_recipients(&self, sender: i32) -> SmallVec<[i32; 32]> {
+        let sender_channel = self.players.get(&sender).map(|p| p.voice_channel).unwrap_or(0);
+        let mut out = SmallVec::new();
+
+        iter_dashmap(&self.players, |(id, p)| {
+            if *id != sender
+                && (sender_channel == 0
+                    || p.voice_channel == 0
+                    || p.voice_channel == sender_channel)
+            {
+                out.push(*id);
+            }
+        });
+
+        out
+    }
+
+    pub fn set_wants_hidden(&self, account_id: i32, hidden: bool) {
+        if let Some(mut player) = self.players.get_mut(&account_id) {
+            player.wants_hidden = hidden;
+        }
+    }
+
     pub fn update_meta(&self, account_id: i32, meta: PlayerLevelMeta) {
         if let Some(mut player) = self.players.get_mut(&account_id) {
             player.meta = meta;
@@ -340,6 +948,187 @@ impl GameSession {
         self.players.get(&account_id).map(|x| x.meta)
     }
 
+    /// Returns a snapshot of the states of all players in the session.
+    ///
+    /// The snapshot is shared between all recipients and only rebuilt once it's older than `max_age` (usually one tick),
+    /// so the player map is iterated (and locked) once per tick instead of once per incoming packet.
+    /// Culling still happens per recipient when encoding, since it depends on the recipient's camera,
+    /// and players that went stale are marked as such if `stale_timeout` is set.
+    /// With more than `max_visible_players` players (unless 0), the snapshot also carries a view scale for everyone,
+    /// and large sessions get a spatial index built from the snapshot positions, so it agrees with what is sent out.
+    pub fn build_tick_snapshot(
+        &self,
+        max_age: Duration,
+        stale_timeout: Option<Duration>,
+        max_visible_players: usize,
+    ) -> Arc<TickSnapshot> {
+        let previous = self.tick_snapshot.load_full();
+        let previous_cells =
+            previous.as_ref().and_then(|s| s.index.as_ref()).map_or(0, |g| g.cell_count());
+
+        if let Some(snapshot) = &previous
+            && snapshot.built_at.elapsed() < max_age
+        {
+            return snapshot.clone();
+        }
+
+        // while paused, keep the states from the previous snapshot, so nobody appears to move.
+        // players that joined since then are shown at their current state, and ones that left are gone
+        let paused_states: Option<FxHashMap<i32, PlayerState>> = previous
+            .filter(|_| self.is_paused())
+            .map(|s| s.players.iter().map(|p| (p.state.account_id, p.state)).collect());
+
+        let mut players = Vec::with_capacity(self.players.len());
+
+        iter_dashmap(&self.players, |(id, p)| {
+            // frozen players are not shown to anyone
+            if p.frozen {
+                return;
+            }
+
+            let state = paused_states.as_ref().and_then(|s| s.get(id).copied()).unwrap_or(p.state);
+
+            players.push(PlayerSnapshot {
+                state,
+                wants_hidden: p.wants_hidden,
+                stale: stale_timeout.is_some_and(|t| p.is_stale(t)),
+            });
+        });
+
+        let view_scale = self.view_scale(players.len(), max_visible_players);
+
+        let index = (players.len() >= SPATIAL_INDEX_MIN_PLAYERS).then(|| {
+            let mut grid = SpatialGrid::with_capacity(SPATIAL_INDEX_CELL_SIZE, previous_cells);
+            for p in &players {
+                grid.insert(&p.state);
+            }
+            grid
+        });
+
+        let snapshot = Arc::new(TickSnapshot {
+            built_at: Instant::now(),
+            players,
+            view_scale,
+            index,
+        });
+
+        self.tick_snapshot.store(Some(snapshot.clone()));
+
+        snapshot
+    }
+
+    /// Returns how much to shrink view distances so that about `max_visible` of `player_count` players are visible,
+    /// assuming they are spread evenly. Computed once per snapshot, so it's the same for every recipient.
+    fn view_scale(&self, player_count: usize, max_visible: usize) -> Option<f32> {
+        if max_visible == 0 || player_count <= max_visible {
+            if self.view_scaled.swap(false, Ordering::Relaxed) {
+                info!(
+                    sid = self.id,
+                    "{player_count} players left, no longer scaling view distance"
+                );
+            }
+
+            return None;
+        }
+
+        if !self.view_scaled.swap(true, Ordering::Relaxed) {
+            info!(
+                sid = self.id,
+                "{player_count} players in the session, scaling view distance to show at most ~{max_visible}"
+            );
+        }
+
+        // the amount of visible players grows with the area, so scale the radius by the square root
+        Some((max_visible as f32 / player_count as f32).sqrt())
+    }
+
+    /// Writes a replayable binary snapshot of all player states and counters, see `session_snapshot` for the format.
+    #[cfg(feature = "admin-api")]
+    pub fn write_snapshot(&self, writer: &mut HeapByteWriter) {
+        let mut players = Vec::with_capacity(self.players.len());
+        iter_dashmap(&self.players, |(_, p)| players.push(p.state));
+
+        let mut counters = Vec::new();
+        if let Some(triggers) = self.triggers.get() {
+            iter_dashmap(&triggers.values, |(key, value)| counters.push((*key, *value)));
+        }
+
+        players.sort_by_key(|p| p.account_id);
+        counters.sort_unstable();
+
+        SessionSnapshot {
+            session_id: self.id,
+            platformer: self.platformer,
+            editor_collab: self.editor_collab,
+            owner: self.owner(),
+            counters,
+            players,
+        }
+        .write(writer);
+    }
+
+    /// Returns a human readable dump of the entire session state, for debugging desyncs.
+    /// Everything is copied out first, so map locks are only held for as long as copying takes.
+    pub fn debug_dump(&self) -> String {
+        use std::fmt::Write;
+
+        struct PlayerDump {
+            account_id: i32,
+            state: PlayerState,
+            wants_hidden: bool,
+            frozen: bool,
+            pending_counters: usize,
+            pending_events: Vec<Arc<str>>,
+        }
+
+        let mut players = Vec::with_capacity(self.players.len());
+
+        iter_dashmap(&self.players, |(id, p)| {
+            players.push(PlayerDump {
+                account_id: *id,
+                state: p.state,
+                wants_hidden: p.wants_hidden,
+                frozen: p.frozen,
+                pending_counters: p.unread_counter_values.len(),
+                pending_events: p.unread_events.iter().map(|e| e.id.clone()).collect(),
+            });
+        });
+
+        let mut counters = Vec::new();
+        if let Some(triggers) = self.triggers.get() {
+            iter_dashmap(&triggers.values, |(key, value)| counters.push((*key, *value)));
+        }
+
+        players.sort_by_key(|p| p.account_id);
+        counters.sort_unstable();
+
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "session {} (owner {}, platformer: {}, editor collab: {}, age: {:?})",
+            self.id,
+            self.owner(),
+            self.platformer,
+            self.editor_collab,
+            self.created_at.elapsed()
+        );
+
+        let _ = writeln!(out, "counters ({}): {counters:?}", counters.len());
+        let _ = writeln!(out, "players ({}):", players.len());
+
+        for p in &players {
+            let _ = writeln!(
+                out,
+                "- {} (hidden: {}, frozen: {}, pending counters: {}, pending events: {:?})",
+                p.account_id, p.wants_hidden, p.frozen, p.pending_counters, p.pending_events
+            );
+            let _ = writeln!(out, "  {:?}", p.state);
+        }
+
+        out
+    }
+
     pub fn for_every_player<F: FnMut(&GamePlayerState)>(&self, mut f: F) {
         iter_dashmap(&self.players, |p| f(p.1));
     }
@@ -352,15 +1141,31 @@ impl GameSession {
         self.player_ids.lock().iter().copied().collect()
     }
 
+    fn record_dropped(&self, count: u64) {
+        if count != 0
+            && let Some(manager) = self.manager.upgrade()
+        {
+            manager.stats.dropped_events.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
     pub fn notify_counter_change(&self, item_id: u32, value: i32) {
+        let mut dropped = 0;
+
         iter_dashmap_mut(&self.players, |p| {
-            p.1.push_counter_change(item_id, value);
+            if !p.1.push_counter_change(item_id, value, self.queue_limits.max_counters) {
+                dropped += 1;
+            }
         });
+
+        self.record_dropped(dropped);
     }
 
     pub fn notify_counter_change_one(&self, player: i32, item_id: u32, value: i32) -> bool {
         if let Some(mut player) = self.players.get_mut(&player) {
-            player.push_counter_change(item_id, value);
+            if !player.push_counter_change(item_id, value, self.queue_limits.max_counters) {
+                self.record_dropped(1);
+            }
             true
         } else {
             false
@@ -370,31 +1175,47 @@ impl GameSession {
     pub fn push_event(&self, player_id: i32, event: OwnedEvent) {
         trace!(sid = self.id, "pushed event {} to {player_id}", event.id);
 
-        if let Some(mut player) = self.players.get_mut(&player_id) {
-            player.push_event(event);
+        if let Some(mut player) = self.players.get_mut(&player_id)
+            && !player.push_event(event, self.queue_limits.max_events)
+        {
+            self.record_dropped(1);
         }
     }
 
     pub fn push_event_to_all(&self, event: OwnedEvent) {
         trace!(sid = self.id, "pushed event {} to all", event.id);
 
+        let mut dropped = 0;
+
         iter_dashmap_mut(&self.players, |p| {
-            p.1.push_event(event.clone());
+            if !p.1.push_event(event.clone(), self.queue_limits.max_events) {
+                dropped += 1;
+            }
         });
+
+        self.record_dropped(dropped);
     }
 
     pub fn push_event_to_all_except(&self, event: OwnedEvent, except: i32) {
         trace!(sid = self.id, "pushed event {} to all except {except}", event.id);
 
+        let mut dropped = 0;
+
         iter_dashmap_mut(&self.players, |p| {
-            if p.0 != &except {
-                p.1.push_event(event.clone());
+            if p.0 != &except && !p.1.push_event(event.clone(), self.queue_limits.max_events) {
+                dropped += 1;
             }
         });
+
+        self.record_dropped(dropped);
     }
 
     #[cfg(feature = "scripting")]
     pub fn log_script_message(&self, msg: &str) {
+        if self.stream_logs.load(Ordering::Relaxed) && self.stream_script_log(msg) {
+            return;
+        }
+
         let mut logs = self.logs.lock();
 
         if logs.len() > 2048 {
@@ -411,13 +1232,54 @@ impl GameSession {
         logs.push_back(msg);
     }
 
+    /// Pushes a log line straight to the owner, returns `false` if the owner is not in the session
+    #[cfg(feature = "scripting")]
+    fn stream_script_log(&self, msg: &str) -> bool {
+        let owner = self.owner();
+
+        if !self.players.contains_key(&owner) {
+            return false;
+        }
+
+        let Some(manager) = self.manager.upgrade() else {
+            return false;
+        };
+
+        tracing::debug!(sid = self.id, "[Script] {msg}");
+
+        let line = format!("[{:.3}] {msg}", self.created_at.elapsed().as_secs_f64());
+
+        let event = OwnedEvent::from_encodable(
+            &ScriptLogLineEvent { line: &line },
+            EventOptions {
+                reliable: true,
+                ..Default::default()
+            },
+            &manager.server().handler().event_string_cache,
+        );
+
+        self.push_event(owner, event);
+
+        true
+    }
+
+    #[cfg(feature = "scripting")]
+    pub fn set_stream_logs(&self, enabled: bool) {
+        self.stream_logs.store(enabled, Ordering::Relaxed);
+    }
+
     #[cfg(feature = "scripting")]
     pub fn pop_script_logs(&self) -> Vec<String> {
         self.logs.lock().drain(0..).collect()
     }
 
+    /// Schedules the next script heartbeat, unless the session is paused. Resuming schedules it again.
     #[cfg(feature = "scripting")]
     pub fn schedule_heartbeat(self: &Arc<GameSession>) {
+        if self.is_paused() {
+            return;
+        }
+
         if let Some(manager) = self.manager.upgrade() {
             manager.schedule_heartbeat(self);
         }
@@ -437,3 +1299,206 @@ impl Hash for GameSession {
         state.write_u64(self.id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: QueueLimits = QueueLimits {
+        max_events: 16,
+        max_counters: 16,
+    };
+
+    fn new_manager(max_sessions: usize, empty_grace_secs: u64) -> Arc<SessionManager> {
+        Arc::new(SessionManager::new(max_sessions, 0, empty_grace_secs, LIMITS, None))
+    }
+
+    fn pending_counters(session: &GameSession, player: i32) -> Vec<(u32, i32)> {
+        let mut changes = session.players.get_mut(&player).unwrap().pop_counter_changes(usize::MAX);
+        changes.sort_by_key(|x| x.2);
+        changes.iter().map(|(id, val, _)| (*id, *val)).collect()
+    }
+
+    #[test]
+    fn join_and_leave() {
+        let manager = new_manager(0, 0);
+        let session = manager.get_or_create_session(1, 10, false, false, false).unwrap();
+
+        // joining an existing session does not create a new one
+        let same = manager.get_or_create_session(1, 10, false, false, false).unwrap();
+        assert!(Arc::ptr_eq(&session, &same));
+        assert_eq!(manager.count(), 1);
+
+        session.add_player(10, false);
+        session.add_player(20, false);
+        assert_eq!(session.player_count(), 2);
+        assert!(session.has_player(20));
+
+        session.remove_player(20);
+        assert_eq!(session.player_count(), 1);
+        assert!(!session.has_player(20));
+        assert_eq!(session.get_all_player_ids(), vec![10]);
+    }
+
+    #[test]
+    fn empty_session_is_deleted() {
+        let manager = new_manager(1, 0);
+        let session = manager.get_or_create_session(1, 10, false, false, false).unwrap();
+        session.add_player(10, false);
+
+        manager.delete_session_if_empty(1, false);
+        assert_eq!(manager.count(), 1);
+
+        session.remove_player(10);
+        manager.delete_session_if_empty(1, false);
+        assert_eq!(manager.count(), 0);
+
+        // the slot is released, so a session can be created again
+        assert!(manager.get_or_create_session(2, 10, false, false, false).is_ok());
+    }
+
+    #[test]
+    fn session_over_the_limit_is_refused() {
+        let manager = new_manager(2, 0);
+        manager.get_or_create_session(1, 10, false, false, false).unwrap();
+        manager.get_or_create_session(2, 20, false, false, false).unwrap();
+
+        assert!(matches!(
+            manager.get_or_create_session(3, 30, false, false, false),
+            Err(SessionCreateError::LimitReached)
+        ));
+        assert_eq!(manager.count(), 2);
+
+        // existing sessions can still be joined, and the cap can be bypassed
+        assert!(manager.get_or_create_session(1, 10, false, false, false).is_ok());
+        assert!(manager.get_or_create_session(3, 30, false, false, true).is_ok());
+        assert_eq!(manager.count(), 3);
+    }
+
+    #[test]
+    fn empty_session_is_kept_for_grace_period() {
+        let manager = new_manager(0, 30);
+        let session = manager.get_or_create_session(1, 10, false, false, false).unwrap();
+        session.add_player(10, false);
+        session.remove_player(10);
+
+        manager.delete_session_if_empty(1, false);
+        manager.sweep_empty_sessions();
+        assert_eq!(manager.count(), 1);
+
+        // a player rejoining keeps the session alive even after the grace period
+        *session.emptied_at.lock() = Some(Instant::now() - Duration::from_secs(60));
+        session.add_player(20, false);
+        manager.sweep_empty_sessions();
+        assert_eq!(manager.count(), 1);
+
+        // and once they leave, the old timestamp must not get the session deleted before it's marked empty again
+        session.remove_player(20);
+        manager.sweep_empty_sessions();
+        assert_eq!(manager.count(), 1);
+
+        manager.delete_session_if_empty(1, false);
+        manager.sweep_empty_sessions();
+        assert_eq!(manager.count(), 1);
+
+        *session.emptied_at.lock() = Some(Instant::now() - Duration::from_secs(60));
+        manager.sweep_empty_sessions();
+        assert_eq!(manager.count(), 0);
+    }
+
+    #[test]
+    fn owner_transfers_to_longest_present_player() {
+        let manager = new_manager(0, 0);
+        let session = manager.get_or_create_session(1, 1, false, false, false).unwrap();
+
+        for id in [1, 2, 3] {
+            session.add_player(id, false);
+        }
+
+        // only the owner leaving transfers ownership
+        assert_eq!(session.transfer_owner_from(2), None);
+        assert_eq!(session.owner(), 1);
+
+        assert_eq!(session.transfer_owner_from(1), Some(2));
+        session.remove_player(1);
+        assert_eq!(session.owner(), 2);
+
+        session.remove_player(3);
+        assert_eq!(session.transfer_owner_from(2), None);
+        assert_eq!(session.owner(), 2);
+    }
+
+    #[test]
+    fn link_needs_both_players() {
+        let manager = new_manager(0, 0);
+        let session = manager.get_or_create_session(1, 1, false, false, false).unwrap();
+
+        assert_eq!(session.request_link(1, 2), LinkRequestOutcome::Requested);
+        // an unanswered request is not a link
+        assert_eq!(session.unlink_player(1), None);
+
+        assert_eq!(session.request_link(1, 2), LinkRequestOutcome::Requested);
+        assert_eq!(session.request_link(2, 1), LinkRequestOutcome::Linked);
+
+        // a third player can't break up the link
+        assert_eq!(session.request_link(3, 2), LinkRequestOutcome::Refused);
+        assert_eq!(session.request_link(1, 3), LinkRequestOutcome::Refused);
+
+        assert_eq!(session.unlink_player(1), Some(2));
+        assert_eq!(session.unlink_player(2), None);
+    }
+
+    #[test]
+    fn partner_is_told_when_linked_player_leaves() {
+        let manager = new_manager(0, 0);
+        let session = manager.get_or_create_session(1, 1, false, false, false).unwrap();
+        let cache = EventStringCache::new();
+        session.add_player(1, false);
+        session.add_player(2, false);
+
+        session.request_link(1, 2);
+        assert_eq!(session.request_link(2, 1), LinkRequestOutcome::Linked);
+
+        session.remove_player(1);
+        assert_eq!(session.unlink_leaving_player(1, &cache), Some(2));
+
+        let partner = session.players.get(&2).unwrap();
+        assert_eq!(partner.unread_events.len(), 1);
+
+        let event = &partner.unread_events[0];
+        assert_eq!(&*event.id, "globed/2p.unlink");
+        assert_eq!(event.options.sent_by_player, NonZero::new(1));
+        drop(partner);
+
+        // nothing is sent once the link is gone
+        assert_eq!(session.unlink_leaving_player(1, &cache), None);
+    }
+
+    #[test]
+    fn counter_changes_reach_all_players() {
+        let manager = new_manager(0, 0);
+        let session = manager.get_or_create_session(1, 1, false, false, false).unwrap();
+        session.add_player(1, false);
+        session.add_player(2, false);
+
+        let change = |item_id, r#type| {
+            let (item_id, value) =
+                session.triggers().handle_change(&CounterChangeEvent { item_id, r#type });
+            session.notify_counter_change(item_id, value);
+        };
+
+        change(3, CounterChangeType::Set(5));
+        change(4, CounterChangeType::Add(2));
+        change(3, CounterChangeType::Add(1));
+
+        for player in [1, 2] {
+            assert_eq!(pending_counters(&session, player), vec![(4, 2), (3, 6)]);
+        }
+
+        // players joining later get the current values
+        session.add_player(3, false);
+        let mut joined = pending_counters(&session, 3);
+        joined.sort_unstable();
+        assert_eq!(joined, vec![(3, 6), (4, 2)]);
+    }
+}
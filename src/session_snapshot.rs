@@ -0,0 +1,399 @@
+//! Compact binary snapshots of a session's tick state, meant to be replayed offline.
+//!
+//! All integers are little endian. Format (version 1):
+//! - magic `GSNP`, `u8` version
+//! - `u64` session ID, `u8` flags (bit 0 platformer, bit 1 editor collab), `i32` owner
+//! - `u32` counter count, then `(u32 item ID, i32 value)` pairs sorted by item ID
+//! - `u16` player count, then player states sorted by account ID
+//!
+//! A player state is `i32` account ID, `f32` timestamp, `u8` frame number, `u8` death count, `u16` percentage,
+//! `u8` flags, then `u8` kind (0 single, 1 dual) followed by one or two player objects.
+//! A player object is `f32` x, `f32` y, `f32` rotation, `u8` icon type, `u16` flags, `bool` has extended data,
+//! then optionally the extended data (7 `f32` fields and `u8` flags).
+
+use server_shared::qunet::buffers::{ByteReader, ByteReaderError, HeapByteWriter};
+use thiserror::Error;
+
+use crate::player_state::{
+    ExtendedPlayerData, PlayerDataKind, PlayerIconType, PlayerObjectData, PlayerState, Point,
+};
+
+const MAGIC: &[u8; 4] = b"GSNP";
+const VERSION: u8 = 1;
+
+#[allow(unused)]
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("not a session snapshot")]
+    BadMagic,
+    #[error("unsupported snapshot version {0}")]
+    UnsupportedVersion(u8),
+    #[error("snapshot is truncated or malformed")]
+    Truncated,
+    #[error("invalid icon type {0}")]
+    InvalidIconType(u8),
+    #[error("invalid player data kind {0}")]
+    InvalidKind(u8),
+}
+
+impl From<ByteReaderError> for SnapshotError {
+    fn from(_: ByteReaderError) -> Self {
+        Self::Truncated
+    }
+}
+
+/// Decoded contents of a snapshot
+pub struct SessionSnapshot {
+    pub session_id: u64,
+    pub platformer: bool,
+    pub editor_collab: bool,
+    pub owner: i32,
+    pub counters: Vec<(u32, i32)>,
+    pub players: Vec<PlayerState>,
+}
+
+impl SessionSnapshot {
+    pub fn write(&self, writer: &mut HeapByteWriter) {
+        writer.write_bytes(MAGIC);
+        writer.write_u8(VERSION);
+
+        writer.write_u64(self.session_id);
+        writer.write_u8(self.platformer as u8 | (self.editor_collab as u8) << 1);
+        writer.write_i32(self.owner);
+
+        writer.write_u32(self.counters.len() as u32);
+        for (item_id, value) in &self.counters {
+            writer.write_u32(*item_id);
+            writer.write_i32(*value);
+        }
+
+        writer.write_u16(self.players.len() as u16);
+        for player in &self.players {
+            write_player_state(writer, player);
+        }
+    }
+
+    /// Parses a snapshot, the server never reads them back but replay tooling does
+    #[allow(unused)]
+    pub fn read(data: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = ByteReader::new(data);
+
+        if reader.skip_bytes(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        let version = reader.read_u8()?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let session_id = reader.read_u64()?;
+        let flags = reader.read_u8()?;
+        let owner = reader.read_i32()?;
+
+        let counter_count = reader.read_u32()? as usize;
+        // each counter takes 8 bytes, don't trust the count for preallocating
+        let mut counters = Vec::with_capacity(counter_count.min(reader.remaining() / 8));
+        for _ in 0..counter_count {
+            counters.push((reader.read_u32()?, reader.read_i32()?));
+        }
+
+        let player_count = reader.read_u16()? as usize;
+        let mut players = Vec::with_capacity(player_count);
+        for _ in 0..player_count {
+            players.push(read_player_state(&mut reader)?);
+        }
+
+        Ok(Self {
+            session_id,
+            platformer: flags & 1 != 0,
+            editor_collab: flags & 2 != 0,
+            owner,
+            counters,
+            players,
+        })
+    }
+}
+
+fn pack_flags(flags: &[bool]) -> u16 {
+    flags.iter().enumerate().fold(0, |acc, (i, f)| acc | (*f as u16) << i)
+}
+
+fn flag(flags: u16, bit: u16) -> bool {
+    flags & (1 << bit) != 0
+}
+
+fn write_player_state(writer: &mut HeapByteWriter, state: &PlayerState) {
+    writer.write_i32(state.account_id);
+    writer.write_f32(state.timestamp);
+    writer.write_u8(state.frame_number);
+    writer.write_u8(state.death_count);
+    writer.write_u16(state.percentage);
+    writer.write_u8(pack_flags(&[
+        state.is_dead,
+        state.is_paused,
+        state.is_practicing,
+        state.is_in_editor,
+        state.is_editor_building,
+        state.is_last_death_real,
+    ]) as u8);
+
+    match &state.data_kind {
+        PlayerDataKind::Single { player } => {
+            writer.write_u8(0);
+            write_player_object(writer, player);
+        }
+
+        PlayerDataKind::Dual { player1, player2 } => {
+            writer.write_u8(1);
+            write_player_object(writer, player1);
+            write_player_object(writer, player2);
+        }
+    }
+}
+
+#[allow(unused)]
+fn read_player_state(reader: &mut ByteReader<'_>) -> Result<PlayerState, SnapshotError> {
+    let account_id = reader.read_i32()?;
+    let timestamp = reader.read_f32()?;
+    let frame_number = reader.read_u8()?;
+    let death_count = reader.read_u8()?;
+    let percentage = reader.read_u16()?;
+    let flags = reader.read_u8()? as u16;
+
+    let data_kind = match reader.read_u8()? {
+        0 => PlayerDataKind::Single {
+            player: read_player_object(reader)?,
+        },
+
+        1 => PlayerDataKind::Dual {
+            player1: read_player_object(reader)?,
+            player2: read_player_object(reader)?,
+        },
+
+        x => return Err(SnapshotError::InvalidKind(x)),
+    };
+
+    Ok(PlayerState {
+        account_id,
+        timestamp,
+        frame_number,
+        death_count,
+        percentage,
+        is_dead: flag(flags, 0),
+        is_paused: flag(flags, 1),
+        is_practicing: flag(flags, 2),
+        is_in_editor: flag(flags, 3),
+        is_editor_building: flag(flags, 4),
+        is_last_death_real: flag(flags, 5),
+        data_kind,
+    })
+}
+
+fn write_player_object(writer: &mut HeapByteWriter, obj: &PlayerObjectData) {
+    writer.write_f32(obj.position.x);
+    writer.write_f32(obj.position.y);
+    writer.write_f32(obj.rotation);
+    writer.write_u8(obj.icon_type as u8);
+    writer.write_u16(pack_flags(&[
+        obj.is_visible,
+        obj.is_looking_left,
+        obj.is_upside_down,
+        obj.is_dashing,
+        obj.is_mini,
+        obj.is_grounded,
+        obj.is_stationary,
+        obj.is_falling,
+        obj.is_rotating,
+        obj.is_sideways,
+        obj.did_just_jump,
+        obj.is_flipped,
+        obj.is_holding,
+    ]));
+
+    writer.write_bool(obj.ext_data.is_some());
+
+    if let Some(ext) = &obj.ext_data {
+        writer.write_f32(ext.velocity.x);
+        writer.write_f32(ext.velocity.y);
+        writer.write_f32(ext.acceleration);
+        writer.write_f32(ext.fall_start_y);
+        writer.write_f32(ext.gravity_mod);
+        writer.write_f32(ext.gravity);
+        writer.write_f32(ext.fall_speed);
+        writer.write_u8(pack_flags(&[
+            ext.accelerating,
+            ext.is_on_ground2,
+            ext.touched_pad,
+            ext.maybe_falling,
+            ext.is_on_ground4,
+        ]) as u8);
+    }
+}
+
+#[allow(unused)]
+fn read_player_object(reader: &mut ByteReader<'_>) -> Result<PlayerObjectData, SnapshotError> {
+    let position = Point::new(reader.read_f32()?, reader.read_f32()?);
+    let rotation = reader.read_f32()?;
+
+    let icon_type = match reader.read_u8()? {
+        0 => PlayerIconType::Unknown,
+        1 => PlayerIconType::Cube,
+        2 => PlayerIconType::Ship,
+        3 => PlayerIconType::Ball,
+        4 => PlayerIconType::Ufo,
+        5 => PlayerIconType::Wave,
+        6 => PlayerIconType::Robot,
+        7 => PlayerIconType::Spider,
+        8 => PlayerIconType::Swing,
+        9 => PlayerIconType::Jetpack,
+        x => return Err(SnapshotError::InvalidIconType(x)),
+    };
+
+    let flags = reader.read_u16()?;
+
+    let ext_data = if reader.read_bool()? {
+        let velocity = Point::new(reader.read_f32()?, reader.read_f32()?);
+        let acceleration = reader.read_f32()?;
+        let fall_start_y = reader.read_f32()?;
+        let gravity_mod = reader.read_f32()?;
+        let gravity = reader.read_f32()?;
+        let fall_speed = reader.read_f32()?;
+        let ext_flags = reader.read_u8()? as u16;
+
+        Some(ExtendedPlayerData {
+            velocity,
+            accelerating: flag(ext_flags, 0),
+            acceleration,
+            fall_start_y,
+            is_on_ground2: flag(ext_flags, 1),
+            gravity_mod,
+            gravity,
+            touched_pad: flag(ext_flags, 2),
+            maybe_falling: flag(ext_flags, 3),
+            fall_speed,
+            is_on_ground4: flag(ext_flags, 4),
+        })
+    } else {
+        None
+    };
+
+    Ok(PlayerObjectData {
+        position,
+        rotation,
+        icon_type,
+        is_visible: flag(flags, 0),
+        is_looking_left: flag(flags, 1),
+        is_upside_down: flag(flags, 2),
+        is_dashing: flag(flags, 3),
+        is_mini: flag(flags, 4),
+        is_grounded: flag(flags, 5),
+        is_stationary: flag(flags, 6),
+        is_falling: flag(flags, 7),
+        is_rotating: flag(flags, 8),
+        is_sideways: flag(flags, 9),
+        did_just_jump: flag(flags, 10),
+        is_flipped: flag(flags, 11),
+        is_holding: flag(flags, 12),
+        ext_data,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player_object(x: f32, ext: bool) -> PlayerObjectData {
+        PlayerObjectData {
+            position: Point::new(x, -x),
+            rotation: 90.0,
+            icon_type: PlayerIconType::Wave,
+            is_visible: true,
+            is_upside_down: true,
+            is_holding: true,
+            ext_data: ext.then(|| ExtendedPlayerData {
+                velocity: Point::new(1.5, -2.5),
+                accelerating: true,
+                gravity: 0.9,
+                is_on_ground4: true,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn snapshot_round_trip() {
+        let snapshot = SessionSnapshot {
+            session_id: 0x1234_5678_9abc,
+            platformer: true,
+            editor_collab: false,
+            owner: 77,
+            counters: vec![(1, -5), (9, 100)],
+            players: vec![
+                PlayerState {
+                    account_id: 10,
+                    timestamp: 12.5,
+                    death_count: 3,
+                    percentage: 4000,
+                    is_dead: true,
+                    is_last_death_real: true,
+                    data_kind: PlayerDataKind::Single {
+                        player: player_object(100.0, true),
+                    },
+                    ..Default::default()
+                },
+                PlayerState {
+                    account_id: 20,
+                    is_practicing: true,
+                    data_kind: PlayerDataKind::Dual {
+                        player1: player_object(5.0, false),
+                        player2: player_object(6.0, true),
+                    },
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let mut writer = HeapByteWriter::new();
+        snapshot.write(&mut writer);
+        let data = writer.written().to_vec();
+
+        let read = SessionSnapshot::read(&data).unwrap();
+
+        assert_eq!(read.session_id, snapshot.session_id);
+        assert!(read.platformer && !read.editor_collab);
+        assert_eq!(read.owner, 77);
+        assert_eq!(read.counters, snapshot.counters);
+        assert_eq!(read.players.len(), 2);
+        assert!(read.players[0].is_dead && read.players[0].is_last_death_real);
+        assert!(!read.players[0].is_paused);
+        assert!(matches!(read.players[1].data_kind, PlayerDataKind::Dual { .. }));
+
+        // everything else is covered by the encoding being identical
+        let mut rewritten = HeapByteWriter::new();
+        read.write(&mut rewritten);
+        assert_eq!(rewritten.written(), data.as_slice());
+    }
+
+    #[test]
+    fn truncated_snapshot_is_rejected() {
+        let mut writer = HeapByteWriter::new();
+        SessionSnapshot {
+            session_id: 1,
+            platformer: false,
+            editor_collab: false,
+            owner: 0,
+            counters: vec![(1, 1)],
+            players: vec![PlayerState::default()],
+        }
+        .write(&mut writer);
+
+        let data = writer.written();
+        assert!(matches!(
+            SessionSnapshot::read(&data[..data.len() - 1]),
+            Err(SnapshotError::Truncated)
+        ));
+        assert!(matches!(SessionSnapshot::read(b"NOPE\x01"), Err(SnapshotError::BadMagic)));
+    }
+}
@@ -0,0 +1,78 @@
+use rustc_hash::{FxHashMap, FxHashSet};
+use smallvec::SmallVec;
+
+use crate::player_state::{PlayerDataKind, PlayerState, Point};
+
+type CellKey = (i32, i32);
+
+/// Uniform grid of player positions, used for finding players near a given point
+/// without checking the distance to every player in the session.
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: FxHashMap<CellKey, SmallVec<[i32; 8]>>,
+}
+
+impl SpatialGrid {
+    pub fn with_capacity(cell_size: f32, cells: usize) -> Self {
+        Self {
+            cell_size,
+            cells: FxHashMap::with_capacity_and_hasher(cells, Default::default()),
+        }
+    }
+
+    fn cell_of(&self, point: &Point) -> CellKey {
+        ((point.x / self.cell_size).floor() as i32, (point.y / self.cell_size).floor() as i32)
+    }
+
+    /// Returns the amount of occupied cells
+    pub fn cell_count(&self) -> usize {
+        self.cells.len()
+    }
+
+    /// Adds a player to the grid, in dual mode it's added to the cells of both players
+    pub fn insert(&mut self, state: &PlayerState) {
+        match &state.data_kind {
+            PlayerDataKind::Single { player } => {
+                let cell = self.cell_of(&player.position);
+                self.cells.entry(cell).or_default().push(state.account_id);
+            }
+
+            PlayerDataKind::Dual { player1, player2 } => {
+                let first = self.cell_of(&player1.position);
+                let second = self.cell_of(&player2.position);
+
+                self.cells.entry(first).or_default().push(state.account_id);
+
+                if second != first {
+                    self.cells.entry(second).or_default().push(state.account_id);
+                }
+            }
+        }
+    }
+
+    /// Adds the IDs of all players in cells that intersect the given circle to `out`.
+    /// This is a superset of the players actually within `radius`, exact checks are left to the caller.
+    pub fn query_into(&self, center: &Point, radius: f32, out: &mut FxHashSet<i32>) {
+        let min = self.cell_of(&Point::new(center.x - radius, center.y - radius));
+        let max = self.cell_of(&Point::new(center.x + radius, center.y + radius));
+
+        let span = (max.0 as i64 - min.0 as i64 + 1) * (max.1 as i64 - min.1 as i64 + 1);
+
+        if span > self.cells.len() as i64 {
+            // huge radius, cheaper to walk every occupied cell than every cell in range
+            for (cell, players) in &self.cells {
+                if (min.0..=max.0).contains(&cell.0) && (min.1..=max.1).contains(&cell.1) {
+                    out.extend(players.iter().copied());
+                }
+            }
+        } else {
+            for x in min.0..=max.0 {
+                for y in min.1..=max.1 {
+                    if let Some(players) = self.cells.get(&(x, y)) {
+                        out.extend(players.iter().copied());
+                    }
+                }
+            }
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use dashmap::DashMap;
 use nohash_hasher::BuildNoHashHasher;
 
-use crate::events::{CounterChangeEvent, CounterChangeType};
+use tracing::warn;
+
+use crate::events::{COUNTER_ITEM_ID_MASK, CounterChangeEvent, CounterChangeType};
 
 #[derive(Default)]
 pub struct TriggerManager {
@@ -10,7 +12,16 @@ pub struct TriggerManager {
 
 impl TriggerManager {
     pub fn handle_change(&self, event: &CounterChangeEvent) -> (u32, i32) {
-        let mut entry = self.values.entry(event.item_id).or_insert(0);
+        // ids that don't fit in 24 bits would be stored under a different id than the one broadcast to clients
+        let item_id = event.item_id & COUNTER_ITEM_ID_MASK;
+        if item_id != event.item_id {
+            warn!(
+                "counter item ID {} does not fit in 24 bits, truncating to {item_id}",
+                event.item_id
+            );
+        }
+
+        let mut entry = self.values.entry(item_id).or_insert(0);
 
         match event.r#type {
             CounterChangeType::Add(val) => {
@@ -35,10 +46,54 @@ impl TriggerManager {
         }
 
         let new_value = *entry;
+        // the entry holds the shard lock, removing while it's alive would deadlock
+        drop(entry);
+
         if new_value == 0 {
-            self.values.remove(&event.item_id);
+            self.values.remove(&item_id);
         }
 
-        (event.item_id, new_value)
+        (item_id, new_value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use server_shared::{events::EventEncode, qunet::buffers::HeapByteWriter};
+
+    use super::*;
+
+    #[test]
+    fn wide_item_id_is_stored_under_broadcast_id() {
+        let triggers = TriggerManager::default();
+        let event = CounterChangeEvent {
+            item_id: (1 << 24) | 5,
+            r#type: CounterChangeType::Set(7),
+        };
+
+        let (item_id, value) = triggers.handle_change(&event);
+        assert_eq!((item_id, value), (5, 7));
+        assert_eq!(triggers.values.get(&5).map(|v| *v), Some(7));
+        assert!(!triggers.values.contains_key(&event.item_id));
+
+        // clients receive the change under the same id it was stored under
+        let mut writer = HeapByteWriter::new();
+        event.encode(&mut writer);
+        let decoded = CounterChangeEvent::decode(writer.written()).unwrap();
+        assert_eq!(decoded.item_id, item_id);
+    }
+
+    #[test]
+    fn zeroed_counter_is_removed() {
+        let triggers = TriggerManager::default();
+
+        let add = |val| CounterChangeEvent {
+            item_id: 3,
+            r#type: CounterChangeType::Add(val),
+        };
+
+        assert_eq!(triggers.handle_change(&add(4)), (3, 4));
+        assert_eq!(triggers.handle_change(&add(-4)), (3, 0));
+        assert!(triggers.values.is_empty());
     }
 }
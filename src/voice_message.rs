@@ -1,10 +1,29 @@
 use std::sync::Arc;
 
 use server_shared::encoding::DataDecodeError;
+use thiserror::Error;
+
+/// Maximum amount of frames in a single voice message
+pub const MAX_VOICE_FRAMES: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum VoiceError {
+    #[error("too many frames ({0}, max {MAX_VOICE_FRAMES})")]
+    TooManyFrames(usize),
+    #[error("message too large ({size} bytes, max {max})")]
+    TooLarge {
+        size: usize,
+        max: usize,
+    },
+    #[error("sender is not in a session")]
+    NotInSession,
+    #[error("malformed voice message: {0}")]
+    Decode(#[from] DataDecodeError),
+}
 
 pub struct VoiceMessage {
     from: i32,
-    splits: heapless::Vec<usize, 16>,
+    splits: heapless::Vec<usize, MAX_VOICE_FRAMES>,
     data: Vec<u8>,
 }
 
@@ -17,19 +36,68 @@ impl VoiceMessage {
         self.from
     }
 
+    /// Returns the individual encoded audio frames
+    #[cfg(feature = "voice-processing")]
+    #[allow(unused)] // for use by voice processors
+    pub fn frames(&self) -> impl Iterator<Item = &[u8]> {
+        let mut offset = 0;
+
+        self.splits.iter().map(move |len| {
+            let frame = &self.data[offset..(offset + len)];
+            offset += len;
+            frame
+        })
+    }
+
+    /// Replaces all audio frames, e.g. after recompressing them
+    #[cfg(feature = "voice-processing")]
+    #[allow(unused)] // for use by voice processors
+    pub fn set_frames<'a>(
+        &mut self,
+        frames: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Result<(), VoiceError> {
+        let mut data = Vec::new();
+        let mut splits = heapless::Vec::new();
+
+        for frame in frames {
+            data.extend_from_slice(frame);
+            splits
+                .push(frame.len())
+                .map_err(|_| VoiceError::TooManyFrames(MAX_VOICE_FRAMES + 1))?;
+        }
+
+        self.data = data;
+        self.splits = splits;
+
+        Ok(())
+    }
+
+    /// Decodes a voice message, rejecting it if it has too many frames or if the frames are over `max_size` bytes in total.
     pub fn decode(
         account_id: i32,
         input: crate::data::voice_data_message::Reader<'_>,
-    ) -> Result<Arc<Self>, DataDecodeError> {
+        max_size: usize,
+    ) -> Result<Arc<Self>, VoiceError> {
         let mut data = Vec::new();
         let mut splits = heapless::Vec::new();
 
-        let total_size =
-            input.get_frames()?.iter().map(|x| x.map(|x| x.len()).unwrap_or(0)).sum::<usize>();
+        let frames = input.get_frames().map_err(DataDecodeError::from)?;
+        if frames.len() as usize > MAX_VOICE_FRAMES {
+            return Err(VoiceError::TooManyFrames(frames.len() as usize));
+        }
+
+        let total_size = frames.iter().map(|x| x.map(|x| x.len()).unwrap_or(0)).sum::<usize>();
+        if total_size > max_size {
+            return Err(VoiceError::TooLarge {
+                size: total_size,
+                max: max_size,
+            });
+        }
+
         data.reserve(total_size);
 
-        for frame in input.get_frames()? {
-            let frame = frame?;
+        for frame in input.get_frames().map_err(DataDecodeError::from)? {
+            let frame = frame.map_err(DataDecodeError::from)?;
             data.extend_from_slice(frame);
             splits.push(frame.len()).map_err(|_| DataDecodeError::ValidationFailed)?;
         }
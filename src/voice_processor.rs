@@ -0,0 +1,21 @@
+//! Hook for inspecting or modifying voice messages before they are relayed to other players.
+//!
+//! To plug in custom processing (e.g. normalization or recompression), implement [`VoiceProcessor`]
+//! and return it from [`create`].
+
+use crate::voice_message::VoiceMessage;
+
+pub trait VoiceProcessor: Send + Sync {
+    /// Called once for every accepted voice message, before it is encoded and broadcast.
+    fn process(&self, _message: &mut VoiceMessage) {}
+}
+
+/// Relays voice messages unchanged
+pub struct NoopVoiceProcessor;
+
+impl VoiceProcessor for NoopVoiceProcessor {}
+
+/// Creates the voice processor used by the server
+pub fn create() -> Box<dyn VoiceProcessor> {
+    Box::new(NoopVoiceProcessor)
+}